@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand, Args};
+use crate::config::validate_profile_name;
 
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -19,6 +20,18 @@ pub struct ForgeArgs {
     #[arg(long = "verbose-hard", global = true, conflicts_with = "verbose")]
     pub verbose_hard: bool,
     
+    /// maximum number of compiler processes running at once [env: RUSTYFORGE_JOBS]
+    #[arg(short = 'j', long, global = true, value_name = "N")]
+    pub jobs: Option<usize>,
+    
+    /// compile every file, even after errors, and report all failures at the end
+    #[arg(long = "keep-going", global = true)]
+    pub keep_going: bool,
+    
+    /// cross compile for a target triple, like `aarch64-linux-gnu`
+    #[arg(long, global = true, value_name = "TRIPLE")]
+    pub target: Option<String>,
+    
     #[command(subcommand)]
     pub command: Command,
 }
@@ -31,12 +44,46 @@ pub enum Command {
     Clean(CleanOptions),
     /// Build and run the project
     Run(RunOptions),
+    /// Build the debug profile and run the executable under gdb (or lldb, with clang)
+    Debug(DebugOptions),
     /// Rebuild the project
     Rebuild(BuildOptions),
     /// Initialize a new, very rusty, forge
     Init(InitOptions),
     /// Discover '.c' files and include directories
     Discover(DiscoverOptions),
+    /// Check the RustyForge.toml for problems
+    CheckConfig,
+    /// Read or edit values in RustyForge.toml, keeping its formatting
+    #[command(subcommand)]
+    Config(ConfigAction),
+    /// Inspect and maintain the build state
+    #[command(subcommand)]
+    Cache(CacheAction),
+}
+
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+pub enum CacheAction {
+    /// Show how many objects and files are tracked, and how many objects are stale
+    Stats,
+    /// List every tracked object with its source, headers and staleness
+    List,
+    /// Check that the cached hashes match the files on disk
+    Verify,
+    /// Remove entries for objects and files that no longer exist
+    Prune,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+pub enum ConfigAction {
+    /// Print the value of a key, e.g. `build.src`
+    Get { key: String },
+    /// Set a key to a value
+    Set { key: String, value: String },
+    /// Add a value to an array, unless it is already contained
+    Add { key: String, value: String },
+    /// Remove a value from an array, or the whole key if no value is given
+    Remove { key: String, value: Option<String> },
 }
 
 #[derive(Args, Debug, PartialEq, Clone)]
@@ -52,17 +99,36 @@ pub struct DiscoverOptions {
 #[derive(Args, Debug, PartialEq, Clone)]
 pub struct RunOptions {
     /// Run the project in debug mode
-    #[arg(long, conflicts_with = "release")]   
+    #[arg(long, conflicts_with_all = ["release", "profile"])]   
     pub debug: bool,
     /// Run the project in release mode
-    #[arg(long, conflicts_with = "debug")]  
+    #[arg(long, conflicts_with_all = ["debug", "profile"])]  
     pub release: bool,
+    /// Run the project with a build profile from RustyForge.toml
+    #[arg(long, value_name = "NAME", value_parser = parse_profile_name)]
+    pub profile: Option<String>,
     /// Clean the project before running
     #[arg(long)]
     pub clean: bool,
     /// Specify the compiler to use
     #[arg(long)]
     pub compiler: Option<String>,
+    /// Name of the executable target to run (defaults to the first one)
+    #[arg(long, value_name = "NAME")]
+    pub bin: Option<String>,
+    /// Arguments to pass to the program
+    #[arg(value_name = "ARGS", trailing_var_arg = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Debug, PartialEq, Clone)]
+pub struct DebugOptions {
+    /// Specify the compiler to use, which also selects the debugger
+    #[arg(long)]
+    pub compiler: Option<String>,
+    /// Name of the executable target to debug (defaults to the first one)
+    #[arg(long, value_name = "NAME")]
+    pub bin: Option<String>,
     /// Arguments to pass to the program
     #[arg(value_name = "ARGS", trailing_var_arg = true)]
     pub args: Vec<String>,
@@ -71,11 +137,14 @@ pub struct RunOptions {
 #[derive(Args, Debug, PartialEq, Clone)]
 pub struct BuildOptions {
     /// specify the build profile as debug (default)
-    #[arg(long, conflicts_with = "release")]
+    #[arg(long, conflicts_with_all = ["release", "profile"])]
     pub debug: bool,
     /// specify the build profile as release
-    #[arg(long, conflicts_with = "debug")]   
+    #[arg(long, conflicts_with_all = ["debug", "profile"])]   
     pub release: bool,
+    /// specify a build profile from RustyForge.toml
+    #[arg(long, value_name = "NAME", value_parser = parse_profile_name)]
+    pub profile: Option<String>,
     /// specify the compiler to use
     #[arg(long)]
     pub compiler: Option<String>,
@@ -100,33 +169,33 @@ pub struct CleanOptions {
     /// clean the libs artifacts only
     #[arg(long)]
     pub libs: bool,
+    /// clean the artifacts of a build profile only
+    #[arg(long, value_name = "NAME", value_parser = parse_profile_name)]
+    pub profile: Option<String>,
 }
 
 pub fn set_command_defaults(cmd: &mut Command) {
     match cmd {
         Command::Build(opts) => {
-            if !opts.debug && !opts.release {
+            if !opts.debug && !opts.release && opts.profile.is_none() {
                 opts.debug = true;
-                return;
             }
         },
         Command::Clean(opts) => {
-            if !opts.debug && !opts.release && !opts.libs {
+            if !opts.debug && !opts.release && !opts.libs && opts.profile.is_none() {
                 opts.debug = true;
                 opts.release = true;
                 opts.libs = true;
             }
         },
         Command::Run(opts) => {
-            if !opts.debug && !opts.release {
+            if !opts.debug && !opts.release && opts.profile.is_none() {
                 opts.debug = true;
-                return;
             }
         }
         Command::Rebuild(opts) => {
-            if !opts.debug && !opts.release {
+            if !opts.debug && !opts.release && opts.profile.is_none() {
                 opts.debug = true;
-                return;
             }
         }
         Command::Init(opts) => {
@@ -134,7 +203,7 @@ pub fn set_command_defaults(cmd: &mut Command) {
                 opts.compiler = Some("gcc".to_string());
             }
         }
-        Command::Discover(_) => {}
+        Command::Debug(_) | Command::Discover(_) | Command::CheckConfig | Command::Config(_) | Command::Cache(_) => {}
     }
 }
 
@@ -144,3 +213,23 @@ pub fn set_command_defaults(cmd: &mut Command) {
 
 
 
+
+/// rejects profile names that would leave the forge directory, before anything is built or removed
+fn parse_profile_name(name: &str) -> anyhow::Result<String> {
+    validate_profile_name(name)?;
+    Ok(name.to_string())
+}
+
+/// name of the build profile selected by the command, `debug` by default
+pub fn profile_name(cmd: &Command) -> String {
+    let (debug, release, profile) = match cmd {
+        Command::Build(opts) | Command::Rebuild(opts) => (opts.debug, opts.release, &opts.profile),
+        Command::Run(opts) => (opts.debug, opts.release, &opts.profile),
+        _ => (true, false, &None),
+    };
+    match profile {
+        Some(name) => name.clone(),
+        None if release && !debug => "release".to_string(),
+        None => "debug".to_string(),
+    }
+}
@@ -0,0 +1,59 @@
+/// The toolchain settings of the standard environment variables, which distribution
+/// packagers and CI systems use to inject compilers and flags. `CC` and `AR` override
+/// the TOML and are overridden by `--compiler`, the flags are added after the flags of the TOML.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BuildEnv {
+    pub cc: Option<String>,
+    pub ar: Option<String>,
+    pub cflags: Vec<String>,
+    pub cppflags: Vec<String>,
+    pub ldflags: Vec<String>,
+}
+
+impl BuildEnv {
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// reads the variables with `var`, empty variables count as unset like in make
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let value = |name: &str| var(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let flags = |name: &str| -> Vec<String> {
+            value(name).map(|v| v.split_whitespace().map(String::from).collect()).unwrap_or_default()
+        };
+        BuildEnv {
+            cc: value("CC"),
+            ar: value("AR"),
+            cflags: flags("CFLAGS"),
+            cppflags: flags("CPPFLAGS"),
+            ldflags: flags("LDFLAGS"),
+        }
+    }
+
+    /// the flags for compiling, in the order of make: `CPPFLAGS`, then `CFLAGS`
+    pub fn compile_flags(&self) -> impl Iterator<Item = &String> {
+        self.cppflags.iter().chain(&self.cflags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_from_vars() {
+        let vars = HashMap::from([
+            ("CC", "clang-18"),
+            ("AR", " "),
+            ("CFLAGS", "-O2  -pipe"),
+            ("CPPFLAGS", "-D_FORTIFY_SOURCE=2"),
+        ]);
+        let env = BuildEnv::from_vars(|name| vars.get(name).map(|v| v.to_string()));
+        assert_eq!(env.cc.as_deref(), Some("clang-18"));
+        assert_eq!(env.ar, None);
+        assert_eq!(env.cflags, ["-O2", "-pipe"]);
+        assert!(env.ldflags.is_empty());
+        assert_eq!(env.compile_flags().collect::<Vec<_>>(), ["-D_FORTIFY_SOURCE=2", "-O2", "-pipe"]);
+    }
+}
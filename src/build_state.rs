@@ -0,0 +1,474 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use anyhow::{Context, Result};
+use tempfile::NamedTempFile;
+use crate::fs_utils::normalize_path;
+use crate::hashes::hash;
+use crate::ui::print_warning;
+
+/// version of the build state format, a state of another version is discarded
+pub const STATE_VERSION: u32 = 1;
+
+/// What the last successful build knows about its objects.
+/// Objects and files are stored as normalized absolute paths.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BuildState {
+    /// missing in states written before the format was versioned
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub objects: BTreeMap<String, ObjectState>,
+    /// metadata and content hash of every input, to skip hashing unchanged files
+    #[serde(default)]
+    pub files: Mutex<BTreeMap<String, FileStamp>>,
+    /// executables and libraries, by the link or archive step that produced them
+    #[serde(default)]
+    pub outputs: BTreeMap<String, OutputState>,
+    /// whether the compiler accepted a flag, by the fingerprint of the probe command
+    #[serde(default)]
+    pub probes: BTreeMap<String, bool>,
+}
+
+impl Default for BuildState {
+    fn default() -> Self {
+        BuildState {
+            version: STATE_VERSION,
+            objects: BTreeMap::new(),
+            files: Mutex::default(),
+            outputs: BTreeMap::new(),
+            probes: BTreeMap::new(),
+        }
+    }
+}
+
+/// The metadata of a file when its content was hashed. While the metadata stays
+/// the same, the content is assumed to be unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+    /// always 0 where there are no inodes
+    #[serde(default)]
+    pub inode: u64,
+    pub hash: String,
+}
+
+impl FileStamp {
+    fn new(metadata: &Metadata, hash: String) -> Option<Self> {
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(FileStamp {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            inode,
+            hash,
+        })
+    }
+
+    fn same_metadata(&self, other: &FileStamp) -> bool {
+        self.size == other.size
+            && self.mtime_secs == other.mtime_secs
+            && self.mtime_nanos == other.mtime_nanos
+            && self.inode == other.inode
+    }
+
+    /// A file modified within the last seconds may be modified again without its
+    /// mtime changing, on file systems with a coarse timestamp resolution.
+    /// Such a stamp is not kept, so the file is hashed again on the next build.
+    fn is_recent(&self) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.as_secs() < self.mtime_secs.saturating_add(2)
+    }
+}
+
+/// the inputs an object was compiled from
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ObjectState {
+    pub source: String,
+    /// headers the source included, as reported by the compiler's depfile
+    #[serde(default)]
+    pub deps: Vec<String>,
+    /// content hashes of the source and the headers, as they were compiled
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+    /// fingerprint of the compile command, the compiler and all inputs
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+/// the inputs an executable or library was linked from
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct OutputState {
+    /// content hashes of the objects and external libraries, as they were linked
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+    /// fingerprint of the link command and all inputs
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+impl BuildState {
+    /// Loads the state. A missing file is an empty state, so is a corrupted state
+    /// or one of another format version, after warning that everything gets rebuilt.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(BuildState::default());
+        }
+        let data = fs::read(path)
+            .with_context(|| format!("Could not read build state: {}", path.display()))?;
+        match serde_json::from_slice::<BuildState>(&data) {
+            Ok(state) if state.version == STATE_VERSION => Ok(state),
+            Ok(state) => {
+                print_warning(&format!(
+                    "build state has format version {}, expected {}, rebuilding everything",
+                    state.version, STATE_VERSION
+                ));
+                Ok(BuildState::default())
+            }
+            Err(e) => {
+                print_warning(&format!("build state is corrupted ({}), rebuilding everything", e));
+                Ok(BuildState::default())
+            }
+        }
+    }
+
+    /// writes the state to a temporary file first, and renames it over the old state,
+    /// so that an interrupted build never leaves a half written state behind
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)?;
+        let mut file = NamedTempFile::new_in(dir)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.as_file().sync_all()?;
+        file.persist(path)
+            .with_context(|| format!("Could not write build state: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn object(&self, object: &Path) -> Option<&ObjectState> {
+        self.objects.get(&normalize_path(object))
+    }
+
+    pub fn record(&mut self, object: &Path, state: ObjectState) {
+        self.objects.insert(normalize_path(object), state);
+    }
+
+    /// drops the entry of an object that was deleted
+    pub fn forget(&mut self, object: &Path) {
+        self.objects.remove(&normalize_path(object));
+    }
+
+    pub fn output(&self, output: &Path) -> Option<&OutputState> {
+        self.outputs.get(&normalize_path(output))
+    }
+
+    pub fn record_output(&mut self, output: &Path, state: OutputState) {
+        self.outputs.insert(normalize_path(output), state);
+    }
+
+    /// Hashes the contents of a file. The hash of the last build is reused without
+    /// reading the file, if its size, mtime and inode did not change since.
+    /// A missing file is hashed as missing, so that it does not match any earlier build.
+    pub fn hash_file(&self, path: &Path) -> String {
+        let key = normalize_path(path);
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return "missing".to_string(),
+        };
+        let files = self.files.lock().expect("file index poisoned");
+        if let (Some(known), Some(current)) = (files.get(&key), FileStamp::new(&metadata, String::new())) {
+            if known.same_metadata(&current) {
+                return known.hash.clone();
+            }
+        }
+        drop(files);
+
+        let Ok(hash) = hash(path) else {
+            return "missing".to_string();
+        };
+        let mut files = self.files.lock().expect("file index poisoned");
+        match FileStamp::new(&metadata, hash.clone()) {
+            Some(stamp) if !stamp.is_recent() => files.insert(key, stamp),
+            _ => files.remove(&key),
+        };
+        hash
+    }
+
+    /// hashes the source and its headers
+    pub fn hash_inputs(&self, source: &Path, deps: &[String]) -> BTreeMap<String, String> {
+        std::iter::once(source)
+            .chain(deps.iter().map(Path::new))
+            .map(|input| (normalize_path(input), self.hash_file(input)))
+            .collect()
+    }
+}
+
+/// The build state of a forge directory, locked for as long as the store lives.
+/// Concurrent builds of the same forge directory wait for each other,
+/// instead of overwriting each other's objects and state.
+pub struct StateStore {
+    path: PathBuf,
+    // the lock is released when the file is closed
+    _lock: File,
+    pub state: BuildState,
+}
+
+impl StateStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock_path = path.with_extension("lock");
+        let lock = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)
+            .with_context(|| format!("Could not open lock file: {}", lock_path.display()))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                println!("Waiting for another build of this project to finish...");
+                lock.lock()?;
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Could not lock: {}", lock_path.display()));
+            }
+        }
+        let state = BuildState::load(path)?;
+        Ok(StateStore { path: path.to_path_buf(), _lock: lock, state })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.state.save(&self.path)
+    }
+}
+
+/// Fingerprints the compilation of an object: the compiler identity (its `--version` output),
+/// the complete command line and the hashes of the source and its headers.
+/// Link steps are fingerprinted the same way, from their objects and libraries.
+pub fn fingerprint(compiler_id: &str, cmd: &Command, hashes: &BTreeMap<String, String>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(compiler_id.as_bytes());
+    hasher.update([0]);
+    hasher.update(cmd.get_program().as_encoded_bytes());
+    for arg in cmd.get_args() {
+        hasher.update([0]);
+        hasher.update(arg.as_encoded_bytes());
+    }
+    for (input, hash) in hashes {
+        hasher.update([0]);
+        hasher.update(input.as_bytes());
+        hasher.update(hash.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Reads a depfile written by `-MMD -MF` and returns the headers of the source.
+/// Relative paths are resolved against `cwd`, the directory the compiler ran in.
+pub fn read_depfile(depfile: &Path, source: &Path, cwd: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(depfile)
+        .with_context(|| format!("Could not read depfile: {}", depfile.display()))?;
+    let source = normalize_path(&cwd.join(source));
+    let mut deps = Vec::new();
+    for dep in parse_depfile(&contents) {
+        let dep = normalize_path(&cwd.join(dep));
+        if dep != source && !deps.contains(&dep) {
+            deps.push(dep);
+        }
+    }
+    Ok(deps)
+}
+
+/// Returns the prerequisites of the first rule of a make style depfile.
+/// Handles line continuations, escaped spaces and Windows drive letters.
+pub fn parse_depfile(contents: &str) -> Vec<PathBuf> {
+    let contents = contents.replace("\\\r\n", " ").replace("\\\n", " ");
+    let rule = contents.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+
+    // the target ends at the first colon followed by whitespace,
+    // a colon of a drive letter like `C:\` is part of the path
+    let bytes = rule.as_bytes();
+    let separator = (0..bytes.len()).find(|&i| {
+        bytes[i] == b':' && bytes.get(i + 1).is_none_or(|b| b.is_ascii_whitespace())
+    });
+    let Some(separator) = separator else {
+        return Vec::new();
+    };
+
+    let mut prerequisites = Vec::new();
+    let mut current = String::new();
+    let mut chars = rule[separator + 1..].chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => {
+                current.push(' ');
+                chars.next();
+            }
+            '$' if chars.peek() == Some(&'$') => {
+                current.push('$');
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    prerequisites.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        prerequisites.push(PathBuf::from(current));
+    }
+    prerequisites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_depfile() {
+        let depfile = "forge/debug/obj/bin/main.o: main.c include/lib.h \\\n include/my\\ header.h\n\ninclude/lib.h:\n";
+        assert_eq!(parse_depfile(depfile), vec![
+            PathBuf::from("main.c"),
+            PathBuf::from("include/lib.h"),
+            PathBuf::from("include/my header.h"),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_depfile_windows_paths() {
+        let depfile = "C:\\project\\forge\\main.o: C:\\project\\main.c \\\r\n C:\\project\\include\\lib.h\r\n";
+        assert_eq!(parse_depfile(depfile), vec![
+            PathBuf::from("C:\\project\\main.c"),
+            PathBuf::from("C:\\project\\include\\lib.h"),
+        ]);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("main.c");
+        fs::write(&source, "int main(void) { return 0; }").unwrap();
+        let state = BuildState::default();
+        let hashes = state.hash_inputs(&source, &[]);
+        let mut cmd = Command::new("gcc");
+        cmd.arg("-c").arg(&source).arg("-O2");
+        let base = fingerprint("gcc 13", &cmd, &hashes);
+        assert_eq!(base, fingerprint("gcc 13", &cmd, &state.hash_inputs(&source, &[])));
+
+        assert_ne!(base, fingerprint("gcc 14", &cmd, &hashes));
+        let mut other_flags = Command::new("gcc");
+        other_flags.arg("-c").arg(&source).arg("-O3");
+        assert_ne!(base, fingerprint("gcc 13", &other_flags, &hashes));
+        let header = normalize_path(&dir.path().join("lib.h"));
+        let with_header = state.hash_inputs(&source, std::slice::from_ref(&header));
+        assert_eq!(with_header.get(&header).map(String::as_str), Some("missing"));
+        assert_ne!(base, fingerprint("gcc 13", &cmd, &with_header));
+
+        fs::write(&source, "int main(void) { return 1; }").unwrap();
+        assert_ne!(hashes, state.hash_inputs(&source, &[]));
+        assert_ne!(base, fingerprint("gcc 13", &cmd, &state.hash_inputs(&source, &[])));
+    }
+
+    #[test]
+    fn test_hash_file_fast_path() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("lib.h");
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let write = |contents: &str| {
+            fs::write(&path, contents).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(an_hour_ago).unwrap();
+        };
+        let state = BuildState::default();
+
+        write("#define A 1");
+        let first = state.hash_file(&path);
+        assert_eq!(first, hash(&path).unwrap());
+
+        // same size and mtime, the content is not read again
+        write("#define B 1");
+        assert_eq!(state.hash_file(&path), first);
+
+        write("#define AB 1");
+        assert_eq!(state.hash_file(&path), hash(&path).unwrap());
+
+        // recently modified files are hashed on every build
+        fs::write(&path, "#define C 1").unwrap();
+        let recent = state.hash_file(&path);
+        assert!(!state.files.lock().unwrap().contains_key(&normalize_path(&path)));
+        assert_eq!(recent, hash(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(state.hash_file(&path), "missing");
+    }
+
+    #[test]
+    fn test_read_depfile_and_state() {
+        let dir = tempdir().unwrap();
+        let depfile = dir.path().join("main.d");
+        fs::write(&depfile, "main.o: main.c include/lib.h ../other/api.h include/lib.h\n").unwrap();
+        let deps = read_depfile(&depfile, Path::new("main.c"), dir.path()).unwrap();
+        let expected: Vec<String> = ["include/lib.h", "../other/api.h"].iter()
+            .map(|d| normalize_path(&dir.path().join(d)))
+            .collect();
+        assert_eq!(deps, expected);
+
+        let state_path = dir.path().join(".forge").join("build_state.json");
+        let mut state = BuildState::load(&state_path).unwrap();
+        assert!(state.objects.is_empty());
+        let object = dir.path().join("main.o");
+        state.record(&object, ObjectState { source: "main.c".to_string(), deps, ..Default::default() });
+        state.save(&state_path).unwrap();
+        let loaded = BuildState::load(&state_path).unwrap();
+        assert_eq!(loaded.object(&object), state.object(&object));
+
+        state.forget(&object);
+        assert!(state.objects.is_empty());
+    }
+
+    #[test]
+    fn test_load_recovers_from_bad_state() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join("build_state.json");
+
+        fs::write(&state_path, "{\"objects\": {\"main.o\": ").unwrap();
+        assert!(BuildState::load(&state_path).unwrap().objects.is_empty());
+
+        // states before the format version was introduced
+        fs::write(&state_path, r#"{"objects": {"main.o": {"source": "main.c"}}}"#).unwrap();
+        let state = BuildState::load(&state_path).unwrap();
+        assert!(state.objects.is_empty());
+        assert_eq!(state.version, STATE_VERSION);
+    }
+
+    #[test]
+    fn test_store_is_exclusive() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join(".forge").join("build_state.json");
+        let mut store = StateStore::open(&state_path).unwrap();
+        store.state.record(Path::new("main.o"), ObjectState::default());
+        store.save().unwrap();
+
+        let lock = File::open(state_path.with_extension("lock")).unwrap();
+        assert!(matches!(lock.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(store);
+        assert!(lock.try_lock().is_ok());
+        lock.unlock().unwrap();
+
+        let store = StateStore::open(&state_path).unwrap();
+        assert!(store.state.object(Path::new("main.o")).is_some());
+        // nothing but the state and the lock is left behind
+        assert_eq!(fs::read_dir(state_path.parent().unwrap()).unwrap().count(), 2);
+    }
+}
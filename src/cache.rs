@@ -0,0 +1,217 @@
+use std::fmt::Display;
+use std::path::Path;
+use anyhow::{bail, Result};
+use colored::Colorize;
+use crate::arguments::CacheAction;
+use crate::build_state::{BuildState, ObjectState, StateStore};
+use crate::hashes::hash;
+
+/// staleness of an object, judged by the inputs recorded for it
+#[derive(Debug, PartialEq)]
+pub enum ObjectStatus {
+    UpToDate,
+    /// the object file was removed
+    MissingObject,
+    /// the source or a header was removed
+    MissingInput(String),
+    /// the source or a header changed since the object was compiled
+    Changed(String),
+}
+
+impl Display for ObjectStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectStatus::UpToDate => write!(f, "up to date"),
+            ObjectStatus::MissingObject => write!(f, "object missing"),
+            ObjectStatus::MissingInput(input) => write!(f, "missing input: {}", input),
+            ObjectStatus::Changed(input) => write!(f, "changed: {}", input),
+        }
+    }
+}
+
+pub fn object_status(state: &BuildState, object: &str, entry: &ObjectState) -> ObjectStatus {
+    if !Path::new(object).is_file() {
+        return ObjectStatus::MissingObject;
+    }
+    for (input, recorded) in &entry.hashes {
+        let current = state.hash_file(Path::new(input));
+        if current == "missing" {
+            return ObjectStatus::MissingInput(input.clone());
+        }
+        if &current != recorded {
+            return ObjectStatus::Changed(input.clone());
+        }
+    }
+    ObjectStatus::UpToDate
+}
+
+/// Files whose cached hash does not match their content. Every file is hashed in full,
+/// ignoring the metadata fast path. Files that no longer exist are left to `prune`.
+pub fn verify(state: &BuildState) -> Vec<String> {
+    let files = state.files.lock().expect("file index poisoned");
+    files.iter()
+        .filter(|(path, stamp)| hash(Path::new(path)).is_ok_and(|h| h != stamp.hash))
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Removes objects whose object file or source no longer exists, outputs that no longer exist,
+/// and files that no longer exist or nothing depends on anymore.
+/// Returns the number of removed objects, outputs and files.
+pub fn prune(state: &mut BuildState) -> (usize, usize, usize) {
+    let objects_before = state.objects.len();
+    state.objects.retain(|object, entry| {
+        Path::new(object).is_file() && Path::new(&entry.source).is_file()
+    });
+    let outputs_before = state.outputs.len();
+    state.outputs.retain(|output, _| Path::new(output).is_file());
+
+    let files = state.files.get_mut().expect("file index poisoned");
+    let files_before = files.len();
+    let (objects, outputs) = (&state.objects, &state.outputs);
+    files.retain(|path, _| {
+        Path::new(path).is_file()
+            && (objects.values().any(|o| o.hashes.contains_key(path))
+                || outputs.values().any(|o| o.hashes.contains_key(path)))
+    });
+    (
+        objects_before - state.objects.len(),
+        outputs_before - state.outputs.len(),
+        files_before - files.len(),
+    )
+}
+
+/// runs `rustyforge cache <action>` on the build state of the forge directory
+pub fn cache_command(forge_dir: &Path, action: &CacheAction) -> Result<()> {
+    let state_path = forge_dir.join(".forge").join("build_state.json");
+    let mut store = StateStore::open(&state_path)?;
+    let cwd = std::env::current_dir()?;
+    let display = |path: &str| -> String {
+        Path::new(path).strip_prefix(&cwd).map(|p| p.display().to_string()).unwrap_or(path.to_string())
+    };
+
+    match action {
+        CacheAction::Stats => {
+            let stale = store.state.objects.iter()
+                .filter(|(object, entry)| object_status(&store.state, object, entry) != ObjectStatus::UpToDate)
+                .count();
+            let files = store.state.files.lock().expect("file index poisoned").len();
+            let size = std::fs::metadata(&state_path).map(|m| m.len()).unwrap_or(0);
+            println!("Build state:   {} ({} bytes)", display(&state_path.to_string_lossy()), size);
+            println!("Objects:       {} ({} stale)", store.state.objects.len(), stale);
+            println!("Outputs:       {}", store.state.outputs.len());
+            println!("Flag probes:   {}", store.state.probes.len());
+            println!("Tracked files: {}", files);
+        }
+        CacheAction::List => {
+            for (object, entry) in &store.state.objects {
+                let status = match object_status(&store.state, object, entry) {
+                    ObjectStatus::UpToDate => ObjectStatus::UpToDate.to_string().green(),
+                    ObjectStatus::MissingInput(input) => ObjectStatus::MissingInput(display(&input)).to_string().yellow(),
+                    ObjectStatus::Changed(input) => ObjectStatus::Changed(display(&input)).to_string().yellow(),
+                    status => status.to_string().yellow(),
+                };
+                println!("{} <- {} [{}]", display(object), display(&entry.source), status);
+                for dep in &entry.deps {
+                    println!("    {}", display(dep));
+                }
+            }
+        }
+        CacheAction::Verify => {
+            let mismatches = verify(&store.state);
+            for path in &mismatches {
+                println!("{} {}", "hash mismatch:".red(), display(path));
+            }
+            if !mismatches.is_empty() {
+                bail!("{} cached hash(es) do not match the files on disk, rebuild to refresh them", mismatches.len());
+            }
+            println!("{}", "All cached hashes match.".green());
+        }
+        CacheAction::Prune => {
+            let (objects, outputs, files) = prune(&mut store.state);
+            store.save()?;
+            println!("Removed {} object(s), {} output(s) and {} file(s) from the build state.", objects, outputs, files);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+    use crate::fs_utils::normalize_path;
+    use tempfile::tempdir;
+
+    /// a state with one object compiled from `main.c` including `lib.h`
+    fn compiled_state(dir: &Path) -> (BuildState, String) {
+        let source = dir.join("main.c");
+        let header = dir.join("lib.h");
+        let object = dir.join("main.o");
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for (path, contents) in [(&source, "#include \"lib.h\""), (&header, "#define A 1"), (&object, "")] {
+            fs::write(path, contents).unwrap();
+            fs::File::options().write(true).open(path).unwrap().set_modified(an_hour_ago).unwrap();
+        }
+        let mut state = BuildState::default();
+        let deps = vec![normalize_path(&header)];
+        let hashes: BTreeMap<String, String> = state.hash_inputs(&source, &deps);
+        state.record(&object, ObjectState {
+            source: normalize_path(&source),
+            deps,
+            hashes,
+            fingerprint: String::new(),
+        });
+        (state, normalize_path(&object))
+    }
+
+    #[test]
+    fn test_object_status() {
+        let dir = tempdir().unwrap();
+        let (state, object) = compiled_state(dir.path());
+        let entry = state.objects[&object].clone();
+        assert_eq!(object_status(&state, &object, &entry), ObjectStatus::UpToDate);
+
+        fs::write(dir.path().join("lib.h"), "#define A 2 // changed").unwrap();
+        let header = normalize_path(&dir.path().join("lib.h"));
+        assert_eq!(object_status(&state, &object, &entry), ObjectStatus::Changed(header.clone()));
+
+        fs::remove_file(dir.path().join("lib.h")).unwrap();
+        assert_eq!(object_status(&state, &object, &entry), ObjectStatus::MissingInput(header));
+
+        fs::remove_file(&object).unwrap();
+        assert_eq!(object_status(&state, &object, &entry), ObjectStatus::MissingObject);
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempdir().unwrap();
+        let (state, _) = compiled_state(dir.path());
+        assert!(verify(&state).is_empty());
+
+        // same size and mtime, only a full hash notices the change
+        let header = dir.path().join("lib.h");
+        let mtime = fs::metadata(&header).unwrap().modified().unwrap();
+        fs::write(&header, "#define B 1").unwrap();
+        fs::File::options().write(true).open(&header).unwrap().set_modified(mtime).unwrap();
+        assert_eq!(verify(&state), vec![normalize_path(&header)]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempdir().unwrap();
+        let (mut state, object) = compiled_state(dir.path());
+        assert_eq!(prune(&mut state), (0, 0, 0));
+
+        fs::remove_file(dir.path().join("lib.h")).unwrap();
+        assert_eq!(prune(&mut state), (0, 0, 1));
+        assert!(state.objects.contains_key(&object));
+
+        fs::remove_file(dir.path().join("main.c")).unwrap();
+        assert_eq!(prune(&mut state), (1, 0, 1));
+        assert!(state.objects.is_empty());
+        assert!(state.files.lock().unwrap().is_empty());
+    }
+}
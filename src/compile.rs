@@ -2,257 +2,285 @@ use crate::utils::*;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use crate::fs_utils::*;
-use crate::config::{CompilerKind, Config};
-use crate::hashes::{cache_hash, file_changed};
-use crate::ui::{print_heating, verbose_command, verbose_command_hard};
+use crate::config::{Config, Profile, Target, TargetKind};
+use crate::build_state::{fingerprint, read_depfile, BuildState, ObjectState, StateStore};
+use crate::flags::{check_flags, FlagKind};
+use crate::ui::{print_heating, verbose_command, verbose_command_hard, verbose_note};
 use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
 use colored::Colorize;
 use crate::arguments::Command::{Run, Rebuild};
 use anyhow::{Result, bail, Context};
 
 pub fn compile(config: &Config) -> Result<()>{
-    if config.compiler == CompilerKind::MSVC{
-        bail!("MSVC is not supported yet");   
-    }
-    
-    // gcc / clang is handled in compile_unix_like()
-    let targets = &config.forge.project.targets;
-    if targets.contains(&"shared".to_string()) {
-        compile_unix_like(config, true)?;
-    }
-    if targets.contains(&"static".to_string()) || targets.contains(&"bin".to_string()) {
-        compile_unix_like(config, false)?;   
+    let mut profile = config.profile()?;
+    let compiler_id = config.toolchain()?.identity()?;
+    // the language settings are compiled after the flags of the profile
+    let language = config.language_flags(&compiler_id)?;
+    profile.cflags.get_or_insert_with(Vec::new).extend(language);
+    if config.args.verbose || config.args.verbose_hard {
+        for origin in config.origins(&compiler_id)? {
+            verbose_note(&origin);
+        }
     }
-    Ok(())
-}
+    let mut store = StateStore::open(&config.build_state_path())?;
 
-pub fn compile_unix_like(config: &Config, shared: bool) -> Result<()>{
-    let to_compile = get_files_to_compile(config, shared)?;
-    let files: Vec<String> = to_compile.iter().map(|(f, _)| f.clone()).collect();
-    let h_files: Vec<PathBuf> = to_compile.iter()
-        .flat_map(|(_, h)| h.clone()).collect();
-    
-    if shared {
-        create_forge_sub_dir("libs/obj")?;
-    }
-    
-    // compile all files (only gcc for now)
-    if !files.is_empty() {
-        print_heating();
-    }
-    
-    files.par_iter().enumerate().try_for_each(|(_, file)| -> Result<()> {
-        let source_path = find_file(&file)
-            .map_err(|e| anyhow::Error::new(e))?;
-        let output_path = get_equivalent_forge_path(&source_path, &config, shared)?;
-        
-        let mut cmd;
-        if config.compiler == CompilerKind::GCC {
-            cmd = Command::new("gcc");
-        }
-        else if config.compiler == CompilerKind::Clang {
-            cmd = Command::new("clang");
+    // collect the work for all targets first, so that caching the hashes of one target
+    // does not hide changed files from the next one
+    let mut plans = Vec::new();
+    for mut target in config.forge.resolve_targets()? {
+        if let Some(cflags) = &target.cflags {
+            target.cflags = Some(check_flags(config, &mut store.state, &compiler_id, &profile, FlagKind::Compile, cflags, &target.name)?);
         }
-        else {
-            bail!(format!("Compiler not supported: {}", config.compiler))  
+        let sources = target.sources(&config.forge_dir)
+            .with_context(|| format!("Could not expand the sources of target: {}", target.name))?;
+        // before anything is compiled, so that no object overwrites another
+        let objects = target_objects(config, &target, &sources)?;
+        let to_compile = get_files_to_compile(config, &profile, &compiler_id, &target, &sources, &store.state)
+            .with_context(|| format!("Could not check target: {}", target.name))?;
+        plans.push((target, objects, to_compile));
+    }
+
+    // gcc / clang is handled in compile_unix_like(), running at most `jobs` compilers at once
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.jobs()?)
+        .build()?;
+    let mut compiled = Vec::new();
+    let mut failures = Vec::new();
+    for (target, _, to_compile) in &plans {
+        let (objects, errors) = pool.install(|| {
+            compile_unix_like(config, &profile, &compiler_id, target, to_compile, &store.state)
+        })?;
+        compiled.extend(objects);
+        failures.extend(errors);
+        if !failures.is_empty() && !config.args.keep_going {
+            break;
         }
-        
-        // add target specific compiler flags
-        add_build_flags(&config.args.command, &mut cmd);
-        // if we are compiling a shared library, add the extra flags
-        if shared {
-            cmd.arg("-fPIC");
+    }
+    if !failures.is_empty() {
+        // keep what did compile, so the next build only retries the failed files
+        for (object, object_state) in compiled {
+            store.state.record(&object, object_state);
         }
-        
-        if let Some(cflags) = config.forge.build.cflags.clone() {
-            for flag in cflags {
-                if is_valid_cflag(&flag) {cmd.arg(flag);}
+        store.save()?;
+        if config.args.keep_going {
+            eprintln!("{}", "Failed to compile:".red().bold());
+            for failure in &failures {
+                eprintln!("  {}", failure);
             }
         }
-        
-        cmd.arg("-c").arg(source_path.clone());
-        
-        for include_dir in &config.forge.build.include_dirs {
-            cmd.arg(format!("-I{}", include_dir));
-        }
-        if let Some(dependencies) = &config.forge.dependencies {
-            for include_dir in &dependencies.include_dirs {
-                cmd.arg(format!("-I{}", include_dir));
+        bail!("{} file(s) failed to compile", failures.len());
+    }
+
+    // record the objects of every target for the linker, and drop objects of removed sources
+    let cwd = std::env::current_dir()?;
+    for (target, objects, _) in &plans {
+        let obj_dir = cwd.join(target_obj_dir(config, target));
+        save_object_manifest(&obj_dir, objects)?;
+        for orphan in prune_orphaned_objects(&obj_dir, objects)? {
+            store.state.forget(&orphan);
+            if config.args.verbose || config.args.verbose_hard {
+                println!("Removed stale object: {}", orphan.display());
             }
         }
-        
-        cmd.arg("-o").arg(output_path);
-        
-        if config.args.verbose {
-            verbose_command(&cmd);
-        }
-        else if config.args.verbose_hard { 
-            verbose_command_hard(&cmd);
-        }
-        
-        let output = cmd.output().expect("Failed to execute gcc");
-        
-        if !output.status.success() {
-            eprintln!(
-                "Furnace not hot enough! Error compiling file: {}:\n{}",
-                file, String::from_utf8_lossy(&output.stderr)
-            );
-            bail!("Error compiling file: {}", file)
-        }
-        else {
-            println!("[{}]", &file.green())
-        }
-        Ok(())
-    })?;
-    // cache .c hashes
-    for c_file in &config.forge.build.src {
-        let absolut_path = find_file(&c_file)?;
-        cache_hash(&absolut_path, std_hash_cache_path()?)?;      
     }
-    // cache .h hashes
-    for h_file in &h_files {
-        cache_hash(&h_file, std_hash_cache_path()
-            .expect("Could not get std hash cache path"))?;      
+
+    // every object keeps the hashes of its own inputs, so that profiles and targets
+    // sharing a source or header each notice a change on their own
+    for (object, object_state) in compiled {
+        store.state.record(&object, object_state);
     }
+    store.save()?;
     Ok(())
 }
 
-pub fn get_files_to_compile(config: &Config, shared: bool)
-    -> Result<Vec<(String, Vec<PathBuf>)>> 
+/// an object file and what it was compiled from
+type CompiledObject = (PathBuf, ObjectState);
+
+/// Compiles the given sources of the target. Every object is compiled with `-MMD -MF`,
+/// the headers from the resulting depfile are returned together with the object.
+/// Returns the compiled objects and the errors of the files that failed. Unless the build
+/// keeps going, no further files are started after the first failure.
+pub fn compile_unix_like(
+    config: &Config,
+    profile: &Profile,
+    compiler_id: &str,
+    target: &Target,
+    files: &[String],
+    state: &BuildState,
+) -> Result<(Vec<CompiledObject>, Vec<anyhow::Error>)>
 {
-    let mut to_compile= Vec::new();
-    
-    for c_file in &config.forge.build.src {
-        // get all relevant file paths
-        let c_file_path = find_file(&c_file)?;
-        let o_file_path = get_equivalent_forge_path(&c_file_path, &config, shared).with_context(|| 
-            format!("Could not get equivalent forge path for file: {}", c_file))?;
-        let h_files = parse_h_dependencies(Path::new(c_file), &config)
-            .with_context(|| format!("Could not parse dependencies for file: {}", c_file))?;
-        let mut compile = false;
-        // if command ist rebuild, compile all files
-        match &config.args.command {
-            Rebuild(_) => compile = true,
-            Run(options) => {
-                if options.clean {
-                    compile = true;
-                }
-            },
-            _ => {}
-        }
-        
-        // if the o file doesn't exist, compile
-        if !o_file_path.exists() {
-            compile = true;
-        }
-        // if the c file has changed, compile
-        if file_changed(&c_file_path, std_hash_cache_path()
-                .expect("Could not get std hash cache path"))
-            .with_context(|| format!("Could not check if file changed: {}", c_file))? 
-        {
-            compile = true;    
+    std::fs::create_dir_all(target_obj_dir(config, target))?;
+
+    // compile all files (only gcc for now)
+    if !files.is_empty() {
+        print_heating();
+    }
+    let cwd = std::env::current_dir()?;
+    let stop = AtomicBool::new(false);
+
+    let results: Vec<Result<CompiledObject>> = files.par_iter().filter_map(|file| {
+        if stop.load(Ordering::Relaxed) {
+            return None;
         }
-        // check if any of the h files have changed
-        for h_file in &h_files {
-            if file_changed(&h_file, std_hash_cache_path()
-                    .expect("Could not get std hash cache path"))
-                .with_context(|| format!("Could not check if file changed: {}", h_file.display()))? 
-            {
-                compile = true;
-                break;
-            }
+        let result = compile_file(config, profile, compiler_id, target, state, &cwd, file);
+        if result.is_err() && !config.args.keep_going {
+            stop.store(true, Ordering::Relaxed);
         }
-        
-        // if compile is true, add the c file to the list of files to compile
-        if compile {
-            to_compile.push((c_file.clone(), h_files));
+        Some(result)
+    }).collect();
+
+    let mut compiled = Vec::new();
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(object) => compiled.push(object),
+            Err(e) => errors.push(e),
         }
     }
-    Ok(to_compile)
+    Ok((compiled, errors))
 }
 
-fn gcc_clang_mm(relpath: &Path, config: &Config) -> Result<String> {
-    let mut cmd = get_compiler_cmd(config)?;
-    let mm_path = normalize_path(relpath);
-    
-    cmd.arg("-MM").arg(mm_path);
-    for dir in &config.forge.build.include_dirs {
-        cmd.arg(format!("-I{}", dir.clone()));
+/// compiles a single source of the target
+fn compile_file(
+    config: &Config,
+    profile: &Profile,
+    compiler_id: &str,
+    target: &Target,
+    state: &BuildState,
+    cwd: &Path,
+    file: &str,
+) -> Result<CompiledObject> {
+    let source_path = find_file(file)
+        .map_err(anyhow::Error::new)?;
+    let output_path = get_equivalent_forge_path(&source_path, config, target)?;
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
     }
-    
+    let depfile = output_path.with_extension("d");
+    let mut cmd = compile_command(config, profile, target, &source_path, &output_path)?;
+
     if config.args.verbose {
         verbose_command(&cmd);   
     }
-    else if config.args.verbose_hard {
+    else if config.args.verbose_hard { 
         verbose_command_hard(&cmd);  
     }
-    
-    let output = cmd.output()?;
-    
-    if output.status.success() {
-        let stdout = String::from_utf8(output.stdout).with_context(|| 
-            format!("Could not convert stdout to string: {}", relpath.display()))?;
-        Ok(stdout)
+
+    let output = cmd.output()
+        .with_context(|| format!("Failed to run the compiler: {}", config.cc()))?;
+
+    if !output.status.success() {
+        eprintln!(
+            "Furnace not hot enough! Error compiling file: {}:\n{}",
+            file, String::from_utf8_lossy(&output.stderr)
+        );
+        bail!("Error compiling file: {}", file)
     }
     else {
-        let stderr = String::from_utf8(output.stderr)
-            .with_context(|| format!("Could not convert stderr to string: {}", relpath.display()))?;
-        bail!(format!("Error compiling file: {}", stderr)); 
+        println!("[{}]", &file.green())
     }
-}
 
-fn parse_h_dependencies(relpath: &Path, config: &Config) -> Result<Vec<PathBuf>> {
-    let output = gcc_clang_mm(relpath, config)?;
-    let parts: Vec<&str> = output.split(':').collect();
-    if parts.len() != 2 {
-        bail!(format!("Could not parse compiler output: {}", output));  
-    }
-    
-    let deps_str = parts[1];
-    
-    let deps_str = deps_str.replace("\\\n", "");
-    let deps = deps_str.split_whitespace();
-    
-    let cwd = std::env::current_dir()
-        .expect("Could not get current working directory");
-    
-    let mut deps_paths = Vec::new();
-    
-    for dep in deps {
-        if dep.ends_with(".h") {
-            let path = Path::new(dep);
-            let abs_path = if path.is_absolute() {
-                path.to_path_buf()
-            }
-            else { 
-                cwd.join(path).canonicalize()?
-            };
-            deps_paths.push(abs_path);
-        }
-    }
-    
-    Ok(deps_paths)
+    let deps = read_depfile(&depfile, &source_path, cwd)?;
+    std::fs::remove_file(&depfile)?;
+    let hashes = state.hash_inputs(&source_path, &deps);
+    let fingerprint = fingerprint(compiler_id, &cmd, &hashes);
+    Ok((output_path, ObjectState { source: normalize_path(&source_path), deps, hashes, fingerprint }))
 }
 
+/// the command compiling a single source of the target into its object
+fn compile_command(config: &Config, profile: &Profile, target: &Target, source_path: &Path, output_path: &Path)
+    -> Result<Command>
+{
+    let toolchain = config.toolchain()?;
+    let mut cmd = toolchain.compiler();
 
-pub fn get_compiler_cmd(config: &Config) -> Result<Command> {
-    if config.compiler == CompilerKind::GCC {
-        Ok(Command::new("gcc"))
-    }
-    else if config.compiler == CompilerKind::Clang {
-        Ok(Command::new("clang"))
+    // add profile specific compiler flags
+    add_profile_cflags(profile, &mut cmd);
+    // if we are compiling a shared library, add the extra flags
+    if target.kind == TargetKind::Shared {
+        cmd.arg("-fPIC");
     }
-    else {
-        bail!(format!("Compiler not supported: {}", config.compiler))  
-    }
-}
 
+    // checked against the flag policy, before compiling
+    cmd.args(target.cflags.iter().flatten());
 
+    // flags injected by the environment come last, so that they win
+    cmd.args(config.env.compile_flags());
 
+    add_include_dirs(config, target, &mut cmd);
 
+    toolchain.compile_args(&mut cmd, source_path, output_path);
+    // let the compiler report the included headers while compiling
+    toolchain.depfile_args(&mut cmd, &output_path.with_extension("d"));
+    Ok(cmd)
+}
 
+/// adds the include directories of the target and the dependencies
+fn add_include_dirs(config: &Config, target: &Target, cmd: &mut Command) {
+    for include_dir in &target.include_dirs {
+        cmd.arg(format!("-I{}", include_dir));
+    }
+    if let Some(dependencies) = &config.forge.dependencies {
+        for include_dir in &dependencies.include_dirs {
+            cmd.arg(format!("-I{}", include_dir));
+        }
+    }
+}
 
+/// Returns the sources of the target that have to be compiled.
+/// A source is stale when its object is missing or unknown to the build state,
+/// when it or one of the headers recorded in its last depfile changed since the object
+/// was compiled, or when the fingerprint of its compile command differs from the last build.
+pub fn get_files_to_compile(
+    config: &Config,
+    profile: &Profile,
+    compiler_id: &str,
+    target: &Target,
+    sources: &[String],
+    state: &BuildState,
+) -> Result<Vec<String>> {
+    let mut to_compile= Vec::new();
 
+    for c_file in sources {
+        // get all relevant file paths
+        let c_file_path = find_file(c_file)?;
+        let o_file_path = get_equivalent_forge_path(&c_file_path, config, target).with_context(||
+            format!("Could not get equivalent forge path for file: {}", c_file))?;
+        let mut compile = false;
+        // if command ist rebuild, compile all files
+        match &config.args.command {
+            Rebuild(_) => compile = true,
+            Run(options) if options.clean => compile = true,
+            _ => {}
+        }
 
+        // if the o file doesn't exist, or its headers are unknown, compile
+        let object = match state.object(&o_file_path) {
+            Some(object) if o_file_path.exists() => object,
+            _ => {
+                to_compile.push(c_file.clone());
+                continue;
+            }
+        };
+        if !compile {
+            // if the c file or any of its h files changed since this object was compiled, compile
+            let hashes = state.hash_inputs(&c_file_path, &object.deps);
+            if hashes != object.hashes {
+                compile = true;
+            }
+            // if the flags, include dirs or the compiler changed, compile
+            else {
+                let cmd = compile_command(config, profile, target, &c_file_path, &o_file_path)?;
+                compile = fingerprint(compiler_id, &cmd, &hashes) != object.fingerprint;
+            }
+        }
 
+        // if compile is true, add the c file to the list of files to compile
+        if compile {
+            to_compile.push(c_file.clone());
+        }
+    }
+    Ok(to_compile)
+}
 
@@ -1,18 +1,36 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use anyhow::{bail, Result};
 use crate::ForgeArgs;
 use crate::fs_utils::std_toml_path;
 use crate::utils::check_compiler;
+use crate::discovery::expand_sources;
 use crate::arguments::Command::*;
+use crate::arguments::profile_name;
+use crate::build_env::BuildEnv;
+use crate::platform::Platform;
+use crate::toolchain::{compiler_version, ClangLike, CrossConfig, CrossTarget, GccLike, Toolchain, ToolchainConfig, Tools};
 
 pub struct Config {
     pub forge: Forge,
     pub args: ForgeArgs,
+    /// the family of the compiler, deciding how the toolchain is driven
     pub compiler: CompilerKind,
+    /// the compiler program, if not the default of its family
+    pub cc: Option<String>,
+    /// directory holding all build output, `forge/` unless shared by a workspace
+    pub forge_dir: PathBuf,
+    /// name of the workspace member this project is built as
+    pub member: Option<String>,
+    /// the compiler, archiver and flags of the environment variables
+    pub env: BuildEnv,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum CompilerKind {
     GCC,
     Clang,
@@ -29,11 +47,29 @@ impl Display for CompilerKind {
     }   
 }
 
-fn determine_compiler_kind(forge: &Forge, args: &ForgeArgs) -> CompilerKind {
+impl CompilerKind {
+    /// The family of a compiler program, by its name. `clang`, `clang-18` or `/opt/llvm/bin/clang`
+    /// are clang-like, every other compiler is expected to understand the command line of gcc.
+    pub fn of_program(program: &str) -> CompilerKind {
+        let name = Path::new(program).file_stem().and_then(|n| n.to_str()).unwrap_or(program);
+        match name {
+            "msvc" | "cl" | "clang-cl" => CompilerKind::MSVC,
+            name if name.contains("clang") => CompilerKind::Clang,
+            _ => CompilerKind::GCC,
+        }
+    }
+}
+
+/// The compiler program and where it was set:
+/// `--compiler`, else `CC`, else `[toolchain] cc`, else `build.compiler`, else gcc
+fn selected_compiler(forge: &Forge, args: &ForgeArgs, env: &BuildEnv) -> (String, &'static str) {
     let arg_compiler = match &args.command { 
         Run(opt) => {
             opt.compiler.clone()
         }
+        Debug(opt) => {
+            opt.compiler.clone()
+        }
         Build(opt) => {
             opt.compiler.clone()
         }
@@ -42,71 +78,510 @@ fn determine_compiler_kind(forge: &Forge, args: &ForgeArgs) -> CompilerKind {
         }
         _ => None,
     };
-    let conf_compiler = &forge.build.compiler;
-    let kind = match arg_compiler { 
-        // prioritize: args > forge
-        Some(comp) => {
-            match_compiler_kind(&comp)
-        }
-        None => {
-            match conf_compiler {
-                Some(comp) => {
-                    match_compiler_kind(comp)
-                }
-                None => CompilerKind::GCC, // default compiler
+    let toolchain_cc = forge.toolchain.as_ref().and_then(|t| t.cc.clone());
+    // prioritize: args > environment > toolchain > build
+    [
+        (arg_compiler, "--compiler"),
+        (env.cc.clone(), "CC"),
+        (toolchain_cc, "[toolchain] cc"),
+        (forge.build.compiler.clone(), "build.compiler"),
+    ]
+        .into_iter()
+        .find_map(|(program, origin)| program.map(|program| (program, origin)))
+        .unwrap_or(("gcc".to_string(), "default")) // default compiler
+}
+
+impl Config {
+    pub fn read(args: &ForgeArgs) -> Result<Self> {
+        let toml_path = std_toml_path()?;
+        let forge = parse_forge_file(toml_path.to_str().unwrap())
+            .map_err(|e| anyhow::anyhow!("Could not parse {}: {}", toml_path.display(), e))?;
+        
+        let env = BuildEnv::from_env();
+        let (program, _) = selected_compiler(&forge, args, &env);
+        let compiler = CompilerKind::of_program(&program);
+        let mut cfg = Config {
+            forge,
+            args: args.clone(),
+            compiler,
+            // the plain family names are the defaults of their toolchain
+            cc: (program != compiler.to_string()).then_some(program),
+            forge_dir: PathBuf::from("forge"),
+            member: None,
+            env,
+        };
+        // only the commands that compile or link need the tools, `clean --target` works without them
+        if let Run(_) | Debug(_) | Build(_) | Rebuild(_) = args.command {
+            check_compiler(&mut cfg)?;
+        }
+        Ok(cfg)
+    }
+
+    /// the compiler program, the default of the compiler family unless one was configured
+    pub fn cc(&self) -> String {
+        self.cc.clone().unwrap_or(self.compiler.to_string())
+    }
+
+    /// the toolchain of the compiler family, with the tools configured in `[toolchain]` or `AR`,
+    /// set up for the target triple when cross compiling
+    pub fn toolchain(&self) -> Result<Box<dyn Toolchain>> {
+        let mut config = self.forge.toolchain.clone().unwrap_or_default();
+        if let Some(ar) = &self.env.ar {
+            config.ar = Some(ar.clone());
+        }
+        let mut tools = Tools::new(self.cc(), &config);
+        if let Some(cross) = self.cross_target() {
+            // clang selects the target with `--target`, gcc needs the cross compiler of the triple
+            let prefix = match self.compiler {
+                CompilerKind::GCC => Some(cross.config.prefix.clone().unwrap_or(format!("{}-", cross.triple))),
+                _ => cross.config.prefix.clone(),
+            };
+            if let Some(prefix) = prefix {
+                tools = tools.with_prefix(&prefix);
             }
+            tools.cross = Some(cross);
         }
-    };
-    kind
-}
+        match self.compiler {
+            CompilerKind::GCC => Ok(Box::new(GccLike { tools })),
+            CompilerKind::Clang => Ok(Box::new(ClangLike { tools })),
+            CompilerKind::MSVC => bail!("MSVC is not supported yet"),
+        }
+    }
+
+    /// The tools and the flags outside of the targets, each with where it was set, for `--verbose`.
+    /// The flags of a target are shown in its commands.
+    pub fn origins(&self, compiler_id: &str) -> Result<Vec<String>> {
+        let tools = self.toolchain()?.tools().clone();
+        let (program, cc_origin) = selected_compiler(&self.forge, &self.args, &self.env);
+        // the compiler falls back to gcc, if the selected one is not available
+        let cc_origin = if program == self.cc() { cc_origin } else { "fallback" };
+        let toolchain = self.forge.toolchain.clone().unwrap_or_default();
+        let ar_origin = match (&self.env.ar, &toolchain.ar) {
+            (Some(_), _) => "AR",
+            (None, Some(_)) => "[toolchain] ar",
+            (None, None) => "default",
+        };
+        let linker_origin = if toolchain.linker.is_some() { "[toolchain] linker" } else { "compiler" };
+        let mut origins = vec![
+            format!("compiler: {} ({})", tools.cc, cc_origin),
+            format!("archiver: {} ({})", tools.ar, ar_origin),
+            format!("linker: {} ({})", tools.linker, linker_origin),
+        ];
 
-fn match_compiler_kind(str: &String) -> CompilerKind {
-    match str.as_str() {
-        "gcc" => CompilerKind::GCC,
-        "clang" => CompilerKind::Clang,
-        "mscv" => CompilerKind::MSVC,
-        _ => {
-            eprintln!("Error: Invalid compiler specified: {}", str);
-            println!("Fallback to default compiler: gcc");
-            CompilerKind::GCC
+        let profile = self.profile()?;
+        let profile_origin = format!("[profile.{}]", self.profile_name());
+        let defines: Vec<String> = profile.defines.iter().flatten().map(|d| format!("-D{}", d)).collect();
+        let mut flags = vec![
+            (profile.cflags.clone().unwrap_or_default(), format!("{} cflags", profile_origin)),
+            (defines, format!("{} defines", profile_origin)),
+            (profile.ldflags.clone().unwrap_or_default(), format!("{} ldflags", profile_origin)),
+            (self.language_flags(compiler_id)?, "[build] c_standard and warnings".to_string()),
+        ];
+        if let Some(triple) = self.target() {
+            let cross = self.cross_target().map(|c| c.config).unwrap_or_default();
+            flags.push((cross.cflags, format!("[cross.{}] cflags", triple)));
+            flags.push((cross.ldflags, format!("[cross.{}] ldflags", triple)));
         }
+        flags.push((self.env.cppflags.clone(), "CPPFLAGS".to_string()));
+        flags.push((self.env.cflags.clone(), "CFLAGS".to_string()));
+        flags.push((self.env.ldflags.clone(), "LDFLAGS".to_string()));
+        for (flags, origin) in flags.into_iter().filter(|(flags, _)| !flags.is_empty()) {
+            origins.push(format!("flags: {} ({})", flags.join(" "), origin));
+        }
+        Ok(origins)
     }
-}
 
-impl Config {
-    pub fn read(args: &ForgeArgs) -> Self {
-        let toml_path = std_toml_path().expect("Could not generate standard TOML path");
-        let forge = parse_forge_file(toml_path.to_str().unwrap())
-            .expect("Could not parse TOML file");
-        
-        let compiler = determine_compiler_kind(&forge, args);
-        let mut cfg = Config { forge, args: args.clone(), compiler };
-        check_compiler(&mut cfg);
-        cfg
+    /// The flags of `build.c_standard`, `build.warnings` and `build.warnings_as_errors`.
+    /// `compiler_id` is the `--version` output of the compiler, a standard the compiler
+    /// version does not know is an error.
+    pub fn language_flags(&self, compiler_id: &str) -> Result<Vec<String>> {
+        let toolchain = self.toolchain()?;
+        let build = &self.forge.build;
+        let mut flags = Vec::new();
+        if let Some(standard) = build.c_standard {
+            flags.push(toolchain.standard_flag(standard, compiler_version(compiler_id))?);
+        }
+        flags.extend(toolchain.warning_flags(build.warnings.unwrap_or(Warnings::Extra), build.warnings_as_errors));
+        Ok(flags)
+    }
+
+    /// the target triple given with `--target`
+    pub fn target(&self) -> Option<&str> {
+        self.args.target.as_deref()
+    }
+
+    /// the target triple with its `[cross.<triple>]` settings, when cross compiling
+    pub fn cross_target(&self) -> Option<CrossTarget> {
+        self.target().map(|triple| CrossTarget {
+            triple: triple.to_string(),
+            config: self.forge.cross.get(triple).cloned().unwrap_or_default(),
+        })
+    }
+
+    /// the platform the build output is for
+    pub fn platform(&self) -> Platform {
+        self.target().map_or(Platform::host(), Platform::of_triple)
+    }
+
+    /// directory of the profiles and libraries, `forge/<triple>/` when cross compiling
+    pub fn output_dir(&self) -> PathBuf {
+        match self.target() {
+            Some(triple) => self.forge_dir.join(triple),
+            None => self.forge_dir.clone(),
+        }
+    }
+
+    /// path of the build state, in `.forge` below the forge directory shared by all profiles and targets
+    pub fn build_state_path(&self) -> PathBuf {
+        self.forge_dir.join(".forge").join("build_state.json")
+    }
+
+    /// number of compiler processes to run at once: `-j`, else `RUSTYFORGE_JOBS`,
+    /// else the available parallelism
+    pub fn jobs(&self) -> Result<usize> {
+        let jobs = match (self.args.jobs, std::env::var("RUSTYFORGE_JOBS")) {
+            (Some(jobs), _) => jobs,
+            (None, Ok(value)) => value.trim().parse()
+                .map_err(|_| anyhow::anyhow!("Invalid RUSTYFORGE_JOBS: {}", value))?,
+            (None, Err(_)) => return Ok(std::thread::available_parallelism().map_or(1, |n| n.get())),
+        };
+        if jobs == 0 {
+            bail!("The number of jobs must be at least 1");
+        }
+        Ok(jobs)
+    }
+
+    /// name of the selected build profile
+    pub fn profile_name(&self) -> String {
+        profile_name(&self.args.command)
+    }
+
+    /// the selected build profile, with its inheritance resolved
+    pub fn profile(&self) -> Result<Profile> {
+        self.forge.resolve_profile(&self.profile_name())
     }
 }
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct Forge {
     pub project: Project,
+    #[serde(default)]
     pub build: Build,
     pub dependencies: Option<Dependencies>,
+    /// named targets, declared as `[[target]]` tables
+    #[serde(default, rename = "target", skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<Target>,
+    /// user defined build profiles, declared as `[profile.<name>]` tables
+    #[serde(default, rename = "profile", skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// how `rustyforge run` starts the executable, declared as `[run]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunSettings>,
+    /// explicit paths of the compiler, archiver and linker
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<ToolchainConfig>,
+    /// settings for cross compiling, declared as `[cross.<triple>]` tables,
+    /// since `[target]` already holds the build targets
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cross: BTreeMap<String, CrossConfig>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Default, Clone)]
+pub struct RunSettings {
+    /// environment variables of the program
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// working directory of the program, relative to the project
+    pub cwd: Option<String>,
+    /// arguments of the program, if none are given on the command line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct Project {
     pub name: String,
+    /// legacy target list, all sharing the sources in `[build]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub targets: Vec<String>,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Default)]
 pub struct Build {
     pub compiler: Option<String>,
+    /// source files, or glob patterns like `src/**/*.c`
+    #[serde(default)]
+    pub src: Vec<String>,
+    /// files or glob patterns excluded from `src`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub include_dirs: Vec<String>,
+    pub output: Option<String>,
+    pub cflags: Option<Vec<String>>,
+    pub ldflags: Option<Vec<String>>,
+    /// how the cflags and ldflags of the targets are checked
+    #[serde(default)]
+    pub flag_policy: FlagPolicy,
+    /// the C standard, the default of the compiler when not set
+    pub c_standard: Option<CStandard>,
+    /// the warning level, `extra` when not set
+    pub warnings: Option<Warnings>,
+    #[serde(default)]
+    pub warnings_as_errors: bool,
+}
+
+/// a C standard, with or without the GNU extensions
+#[derive(Deserialize, Debug, Serialize, PartialEq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum CStandard {
+    C99,
+    C11,
+    C17,
+    C23,
+    Gnu99,
+    Gnu11,
+    Gnu17,
+    Gnu23,
+}
+
+impl Display for CStandard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = if self.is_gnu() { "gnu" } else { "c" };
+        write!(f, "{}{}", prefix, self.year())
+    }
+}
+
+impl CStandard {
+    /// the two digit year of the standard
+    pub fn year(self) -> u32 {
+        match self {
+            CStandard::C99 | CStandard::Gnu99 => 99,
+            CStandard::C11 | CStandard::Gnu11 => 11,
+            CStandard::C17 | CStandard::Gnu17 => 17,
+            CStandard::C23 | CStandard::Gnu23 => 23,
+        }
+    }
+
+    pub fn is_gnu(self) -> bool {
+        matches!(self, CStandard::Gnu99 | CStandard::Gnu11 | CStandard::Gnu17 | CStandard::Gnu23)
+    }
+}
+
+/// `default` keeps the warnings the compiler enables on its own, `none` disables every warning
+#[derive(Deserialize, Debug, Serialize, PartialEq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Warnings {
+    Default,
+    All,
+    Extra,
+    Pedantic,
+    None,
+}
+
+/// `strict` fails the build on flags the compiler rejects, `warn` drops them with a warning,
+/// `off` passes every flag to the compiler unchecked
+#[derive(Deserialize, Debug, Serialize, PartialEq, Copy, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagPolicy {
+    Strict,
+    #[default]
+    Warn,
+    Off,
+}
+
+#[derive(Deserialize, Debug, Serialize, PartialEq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    Bin,
+    Static,
+    Shared,
+}
+
+impl Display for TargetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetKind::Bin => write!(f, "bin"),
+            TargetKind::Static => write!(f, "static"),
+            TargetKind::Shared => write!(f, "shared"),
+        }
+    }
+}
+
+impl TargetKind {
+    pub fn parse(str: &str) -> Result<Self> {
+        match str {
+            "bin" => Ok(TargetKind::Bin),
+            "static" => Ok(TargetKind::Static),
+            "shared" => Ok(TargetKind::Shared),
+            _ => bail!("Unknown target: {} None of [bin, static, shared]", str),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct Target {
+    pub name: String,
+    pub kind: TargetKind,
+    #[serde(default)]
     pub src: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default)]
     pub include_dirs: Vec<String>,
-    pub output: String,
     pub cflags: Option<Vec<String>>,
     pub ldflags: Option<Vec<String>>,
+    pub output: Option<String>,
+}
+
+impl Target {
+    /// name of the produced executable or library, defaults to the target name
+    pub fn output_name(&self) -> &str {
+        self.output.as_deref().unwrap_or(&self.name)
+    }
+
+    /// the source files of the target, with glob patterns expanded and excludes removed
+    pub fn sources(&self, forge_dir: &Path) -> Result<Vec<String>> {
+        expand_sources(Path::new("."), forge_dir, &self.src, &self.exclude)
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Default, Clone, PartialEq)]
+pub struct Profile {
+    pub inherits: Option<String>,
+    pub cflags: Option<Vec<String>>,
+    pub ldflags: Option<Vec<String>>,
+    pub defines: Option<Vec<String>>,
+}
+
+impl Profile {
+    /// the built-in `debug` and `release` profiles
+    pub fn builtin(name: &str) -> Option<Profile> {
+        match name {
+            "debug" => Some(Profile {
+                inherits: None,
+                cflags: Some(to_strings(&["-g", "-O0"])),
+                ldflags: None,
+                defines: Some(to_strings(&["DEBUG"])),
+            }),
+            "release" => Some(Profile {
+                inherits: None,
+                cflags: Some(to_strings(&["-O3"])),
+                ldflags: None,
+                defines: Some(to_strings(&["RELEASE", "NDEBUG"])),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// the profile name is used as the output directory below forge/, so it must stay inside it
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['/', '\\', '.']) || name == "libs" {
+        bail!("Invalid profile name: {}", name);
+    }
+    Ok(())
+}
+
+fn to_strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+impl Forge {
+    /// Resolves a build profile by name, following its `inherits` chain.
+    /// A `[profile.<name>]` table replaces a built-in profile of the same name,
+    /// an inheriting profile appends its flags and defines to those of its parent.
+    pub fn resolve_profile(&self, name: &str) -> Result<Profile> {
+        validate_profile_name(name)?;
+        let mut chain: Vec<String> = Vec::new();
+        let mut current = name.to_string();
+        let mut layers = Vec::new();
+        loop {
+            if chain.contains(&current) {
+                bail!("Profile inheritance cycle: {} -> {}", chain.join(" -> "), current);
+            }
+            let profile = match self.profiles.get(&current) {
+                Some(profile) => profile.clone(),
+                None => Profile::builtin(&current)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown profile: {}", current))?,
+            };
+            chain.push(current);
+            let parent = profile.inherits.clone();
+            layers.push(profile);
+            match parent {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+
+        let mut resolved = Profile::default();
+        for layer in layers.iter().rev() {
+            resolved.cflags = merge_opt(&resolved.cflags, &layer.cflags);
+            resolved.ldflags = merge_opt(&resolved.ldflags, &layer.ldflags);
+            resolved.defines = merge_opt(&resolved.defines, &layer.defines);
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves all targets of the project.
+    /// `[[target]]` tables inherit the include dirs and flags from `[build]`.
+    /// Without any `[[target]]` tables, the legacy `project.targets` list is used,
+    /// with every target sharing the sources and output of `[build]`.
+    pub fn resolve_targets(&self) -> Result<Vec<Target>> {
+        let mut resolved: Vec<Target> = Vec::new();
+        if self.targets.is_empty() {
+            let output = self.build.output.clone()
+                .unwrap_or_else(|| self.project.name.clone());
+            for kind in &self.project.targets {
+                let kind = TargetKind::parse(kind)?;
+                resolved.push(Target {
+                    name: kind.to_string(),
+                    kind,
+                    src: self.build.src.clone(),
+                    exclude: self.build.exclude.clone(),
+                    include_dirs: self.build.include_dirs.clone(),
+                    cflags: self.build.cflags.clone(),
+                    ldflags: self.build.ldflags.clone(),
+                    output: Some(output.clone()),
+                });
+            }
+        }
+        else {
+            for target in &self.targets {
+                let mut target = target.clone();
+                target.include_dirs = merge(&self.build.include_dirs, &target.include_dirs);
+                target.exclude = merge(&self.build.exclude, &target.exclude);
+                target.cflags = merge_opt(&self.build.cflags, &target.cflags);
+                target.ldflags = merge_opt(&self.build.ldflags, &target.ldflags);
+                resolved.push(target);
+            }
+        }
+        for (i, target) in resolved.iter().enumerate() {
+            if resolved[..i].iter().any(|t| t.name == target.name) {
+                bail!("Duplicate target name: {}", target.name);
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+fn merge(base: &[String], extra: &[String]) -> Vec<String> {
+    let mut merged = base.to_vec();
+    for item in extra {
+        if !merged.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+fn merge_opt(base: &Option<Vec<String>>, extra: &Option<Vec<String>>) -> Option<Vec<String>> {
+    match (base, extra) {
+        (None, None) => None,
+        (Some(b), None) => Some(b.clone()),
+        (None, Some(e)) => Some(e.clone()),
+        (Some(b), Some(e)) => Some([b.as_slice(), e.as_slice()].concat()),
+    }
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -122,3 +597,224 @@ pub fn parse_forge_file(path: &str) -> Result<Forge, Box<dyn std::error::Error>>
     let forge: Forge = toml::from_str(&contents)?;
     Ok(forge)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arguments::BuildOptions;
+    use crate::tests::dummy_config;
+
+    #[test]
+    fn test_compiler_kind_of_program() {
+        assert_eq!(CompilerKind::of_program("gcc"), CompilerKind::GCC);
+        assert_eq!(CompilerKind::of_program("gcc-13"), CompilerKind::GCC);
+        assert_eq!(CompilerKind::of_program("tcc"), CompilerKind::GCC);
+        assert_eq!(CompilerKind::of_program("aarch64-linux-gnu-gcc"), CompilerKind::GCC);
+        assert_eq!(CompilerKind::of_program("/opt/llvm/bin/clang"), CompilerKind::Clang);
+        assert_eq!(CompilerKind::of_program("clang-18"), CompilerKind::Clang);
+        assert_eq!(CompilerKind::of_program("msvc"), CompilerKind::MSVC);
+        assert_eq!(CompilerKind::of_program("cl.exe"), CompilerKind::MSVC);
+    }
+
+    #[test]
+    fn test_toolchain_tools() {
+        let mut config = dummy_config(true);
+        assert_eq!(config.toolchain().unwrap().tools().cc, "gcc");
+        config.compiler = CompilerKind::Clang;
+        assert_eq!(config.toolchain().unwrap().tools().cc, "clang");
+
+        config.cc = Some("/opt/llvm/bin/clang".to_string());
+        config.forge.toolchain = Some(ToolchainConfig {
+            cc: None,
+            ar: Some("llvm-ar".to_string()),
+            linker: None,
+        });
+        let toolchain = config.toolchain().unwrap();
+        assert_eq!(toolchain.tools().ar, "llvm-ar");
+        assert_eq!(toolchain.tools().linker, "/opt/llvm/bin/clang");
+
+        config.compiler = CompilerKind::MSVC;
+        assert!(config.toolchain().is_err());
+    }
+
+    #[test]
+    fn test_selected_compiler() {
+        let mut config = dummy_config(true);
+        let mut env = BuildEnv::default();
+        assert_eq!(selected_compiler(&config.forge, &config.args, &env), ("gcc".to_string(), "build.compiler"));
+
+        config.forge.toolchain = Some(ToolchainConfig { cc: Some("gcc-12".to_string()), ..Default::default() });
+        assert_eq!(selected_compiler(&config.forge, &config.args, &env).1, "[toolchain] cc");
+
+        env.cc = Some("clang".to_string());
+        assert_eq!(selected_compiler(&config.forge, &config.args, &env), ("clang".to_string(), "CC"));
+
+        config.args.command = Build(BuildOptions { debug: true, compiler: Some("tcc".to_string()), release: false, profile: None });
+        assert_eq!(selected_compiler(&config.forge, &config.args, &env), ("tcc".to_string(), "--compiler"));
+    }
+
+    #[test]
+    fn test_env_tools_and_origins() {
+        let mut config = dummy_config(true);
+        config.env.ar = Some("llvm-ar".to_string());
+        config.env.cflags = vec!["-O2".to_string(), "-pipe".to_string()];
+        assert_eq!(config.toolchain().unwrap().tools().ar, "llvm-ar");
+        let origins = config.origins("gcc (GCC) 12.2.0").unwrap();
+        assert!(origins.contains(&"archiver: llvm-ar (AR)".to_string()));
+        assert!(origins.contains(&"flags: -O2 -pipe (CFLAGS)".to_string()));
+        assert!(!origins.iter().any(|o| o.ends_with("(LDFLAGS)")));
+    }
+
+    #[test]
+    fn test_cross_target() {
+        let mut config = dummy_config(true);
+        assert_eq!(config.output_dir(), PathBuf::from("forge"));
+        assert_eq!(config.platform(), Platform::host());
+
+        config.args.target = Some("x86_64-w64-mingw32".to_string());
+        assert_eq!(config.output_dir(), PathBuf::from("forge/x86_64-w64-mingw32"));
+        assert_eq!(config.platform(), Platform::Windows);
+        let toolchain = config.toolchain().unwrap();
+        assert_eq!(toolchain.tools().cc, "x86_64-w64-mingw32-gcc");
+        assert_eq!(toolchain.tools().ar, "x86_64-w64-mingw32-ar");
+
+        config.forge.cross.insert("x86_64-w64-mingw32".to_string(), CrossConfig {
+            prefix: Some("mingw-".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(config.toolchain().unwrap().tools().cc, "mingw-gcc");
+
+        // clang is not prefixed, unless configured
+        config.forge.cross.clear();
+        config.compiler = CompilerKind::Clang;
+        assert_eq!(config.toolchain().unwrap().tools().cc, "clang");
+    }
+
+    #[test]
+    fn test_jobs() {
+        let mut config = dummy_config(true);
+        config.args.jobs = Some(3);
+        assert_eq!(config.jobs().unwrap(), 3);
+        config.args.jobs = Some(0);
+        assert!(config.jobs().is_err());
+        // RUSTYFORGE_JOBS is only read without -j, and not set while testing
+        config.args.jobs = None;
+        assert!(config.jobs().unwrap() >= 1);
+    }
+
+    #[test]
+    fn test_resolve_legacy_targets() {
+        let forge: Forge = toml::from_str(r#"
+            [project]
+            name = "demo"
+            targets = ["bin", "static"]
+
+            [build]
+            src = ["main.c"]
+            include_dirs = ["include"]
+            output = "demo"
+        "#).unwrap();
+        let targets = forge.resolve_targets().unwrap();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].kind, TargetKind::Bin);
+        assert_eq!(targets[1].kind, TargetKind::Static);
+        assert_eq!(targets[1].src, vec!["main.c".to_string()]);
+        assert_eq!(targets[1].output_name(), "demo");
+    }
+
+    #[test]
+    fn test_resolve_named_targets() {
+        let forge: Forge = toml::from_str(r#"
+            [project]
+            name = "demo"
+
+            [build]
+            include_dirs = ["include"]
+            cflags = ["-O2"]
+
+            [[target]]
+            name = "core"
+            kind = "static"
+            src = ["core.c"]
+
+            [[target]]
+            name = "app"
+            kind = "bin"
+            src = ["main.c"]
+            include_dirs = ["app/include"]
+            cflags = ["-g"]
+            output = "demo"
+        "#).unwrap();
+        let targets = forge.resolve_targets().unwrap();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].output_name(), "core");
+        assert_eq!(targets[1].output_name(), "demo");
+        assert_eq!(targets[1].include_dirs, vec!["include".to_string(), "app/include".to_string()]);
+        assert_eq!(targets[1].cflags, Some(vec!["-O2".to_string(), "-g".to_string()]));
+    }
+
+    #[test]
+    fn test_resolve_profiles() {
+        let forge: Forge = toml::from_str(r#"
+            [project]
+            name = "demo"
+
+            [profile.release]
+            cflags = ["-O2"]
+
+            [profile.bench]
+            inherits = "release"
+            cflags = ["-flto"]
+            ldflags = ["-flto"]
+            defines = ["BENCH=1"]
+        "#).unwrap();
+        let debug = forge.resolve_profile("debug").unwrap();
+        assert_eq!(debug.defines, Some(vec!["DEBUG".to_string()]));
+        let release = forge.resolve_profile("release").unwrap();
+        assert_eq!(release.cflags, Some(vec!["-O2".to_string()]));
+        assert_eq!(release.defines, None);
+        let bench = forge.resolve_profile("bench").unwrap();
+        assert_eq!(bench.cflags, Some(vec!["-O2".to_string(), "-flto".to_string()]));
+        assert_eq!(bench.ldflags, Some(vec!["-flto".to_string()]));
+        assert!(forge.resolve_profile("missing").is_err());
+    }
+
+    #[test]
+    fn test_resolve_profile_cycle() {
+        let forge: Forge = toml::from_str(r#"
+            [project]
+            name = "demo"
+
+            [profile.a]
+            inherits = "b"
+
+            [profile.b]
+            inherits = "a"
+        "#).unwrap();
+        assert!(forge.resolve_profile("a").is_err());
+    }
+
+    #[test]
+    fn test_resolve_rejects_unknown_and_duplicates() {
+        let unknown: Forge = toml::from_str(r#"
+            [project]
+            name = "demo"
+            targets = ["dll"]
+        "#).unwrap();
+        assert!(unknown.resolve_targets().is_err());
+
+        let duplicate: Forge = toml::from_str(r#"
+            [project]
+            name = "demo"
+
+            [[target]]
+            name = "app"
+            kind = "bin"
+
+            [[target]]
+            name = "app"
+            kind = "static"
+        "#).unwrap();
+        assert!(duplicate.resolve_targets().is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context, Result};
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+/// A RustyForge.toml that can be edited without losing comments, key order or unknown sections.
+/// Keys are addressed by dotted paths like `build.src` or `profile.bench.cflags`.
+/// Inside `[[target]]` tables, a segment selects the table by its `name` or its index,
+/// e.g. `target.app.src` or `target.0.src`.
+pub struct ConfigDocument {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigDocument {
+    pub fn open(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        let doc = contents.parse::<DocumentMut>()
+            .with_context(|| format!("Could not parse {}", path.display()))?;
+        Ok(ConfigDocument { path: path.to_path_buf(), doc })
+    }
+
+    /// an empty document, which is written to the given path on save
+    pub fn new(path: &Path) -> Self {
+        ConfigDocument { path: path.to_path_buf(), doc: DocumentMut::new() }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, self.doc.to_string())
+            .with_context(|| format!("Could not write {}", self.path.display()))?;
+        Ok(())
+    }
+
+    /// the value at the key path, formatted as TOML
+    pub fn get(&self, key_path: &str) -> Result<Option<String>> {
+        let (parents, key) = split_key_path(key_path)?;
+        let mut table: &dyn TableLike = self.doc.as_table();
+        let mut segments = parents.into_iter();
+        while let Some(segment) = segments.next() {
+            let Some(item) = table.get(segment) else {
+                return Ok(None);
+            };
+            table = match child_table(item, segment, &mut segments)? {
+                Some(child) => child,
+                None => return Ok(None),
+            };
+        }
+        Ok(table.get(key).map(format_item))
+    }
+
+    /// sets the key to the value, creating missing tables on the way
+    pub fn set(&mut self, key_path: &str, value: Value) -> Result<()> {
+        let (parents, key) = split_key_path(key_path)?;
+        let table = self.table_mut(&parents)?;
+        match table.get_mut(key) {
+            Some(Item::Value(existing)) => {
+                // keep the formatting around the old value
+                let decor = existing.decor().clone();
+                *existing = value;
+                *existing.decor_mut() = decor;
+            }
+            _ => {
+                table.insert(key, Item::Value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// adds the value to the array at the key path, unless it is already contained.
+    /// Returns whether the value was added.
+    pub fn add(&mut self, key_path: &str, value: Value) -> Result<bool> {
+        let (parents, key) = split_key_path(key_path)?;
+        let table = self.table_mut(&parents)?;
+        let item = table.entry(key).or_insert(Item::Value(Value::Array(Array::new())));
+        let Some(array) = item.as_array_mut() else {
+            bail!("`{}` is not an array", key_path);
+        };
+        if array.iter().any(|v| same_value(v, &value)) {
+            return Ok(false);
+        }
+        array.push(value);
+        Ok(true)
+    }
+
+    /// removes the value from the array at the key path, or the whole key when no value is given.
+    /// Returns whether anything was removed.
+    pub fn remove(&mut self, key_path: &str, value: Option<Value>) -> Result<bool> {
+        let (parents, key) = split_key_path(key_path)?;
+        let table = self.table_mut(&parents)?;
+        let Some(value) = value else {
+            return Ok(table.remove(key).is_some());
+        };
+        let Some(array) = table.get_mut(key).and_then(Item::as_array_mut) else {
+            bail!("`{}` is not an array", key_path);
+        };
+        let before = array.len();
+        array.retain(|v| !same_value(v, &value));
+        Ok(array.len() != before)
+    }
+
+    fn table_mut(&mut self, parents: &[&str]) -> Result<&mut dyn TableLike> {
+        let mut table: &mut dyn TableLike = self.doc.as_table_mut();
+        let mut inline = false;
+        let mut segments = parents.iter().copied();
+        while let Some(segment) = segments.next() {
+            if table.get(segment).is_none() {
+                // tables inside inline tables have to be inline as well
+                let child = if inline {
+                    Item::Value(Value::InlineTable(InlineTable::new()))
+                } else {
+                    let mut child = Table::new();
+                    child.set_implicit(true);
+                    Item::Table(child)
+                };
+                table.insert(segment, child);
+            }
+            let item = table.get_mut(segment).expect("inserted above");
+            inline = item.is_inline_table();
+            table = child_table_mut(item, segment, &mut segments)?;
+        }
+        Ok(table)
+    }
+}
+
+fn split_key_path(key_path: &str) -> Result<(Vec<&str>, &str)> {
+    let mut segments: Vec<&str> = key_path.split('.').collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        bail!("Invalid key path: {}", key_path);
+    }
+    let key = segments.pop().expect("split yields at least one segment");
+    Ok((segments, key))
+}
+
+/// whether a table of an array of tables is selected by the segment
+fn selects(index: usize, table: &Table, segment: &str) -> bool {
+    index.to_string() == segment || table.get("name").and_then(Item::as_str) == Some(segment)
+}
+
+/// the table below the item; for an array of tables, the next segment selects the table
+fn child_table<'a, 'k>(
+    item: &'a Item,
+    segment: &str,
+    segments: &mut impl Iterator<Item = &'k str>,
+) -> Result<Option<&'a dyn TableLike>> {
+    if let Some(tables) = item.as_array_of_tables() {
+        let Some(selector) = segments.next() else {
+            bail!("`{}` is an array of tables, select one by name or index", segment);
+        };
+        return Ok(tables.iter().enumerate()
+            .find(|(i, t)| selects(*i, t, selector))
+            .map(|(_, t)| t as &dyn TableLike));
+    }
+    match item.as_table_like() {
+        Some(table) => Ok(Some(table)),
+        None => bail!("`{}` is not a table", segment),
+    }
+}
+
+fn child_table_mut<'a, 'k>(
+    item: &'a mut Item,
+    segment: &str,
+    segments: &mut impl Iterator<Item = &'k str>,
+) -> Result<&'a mut dyn TableLike> {
+    if item.is_array_of_tables() {
+        let Some(selector) = segments.next() else {
+            bail!("`{}` is an array of tables, select one by name or index", segment);
+        };
+        let tables = item.as_array_of_tables_mut().expect("checked above");
+        return match tables.iter_mut().enumerate().find(|(i, t)| selects(*i, t, selector)) {
+            Some((_, table)) => Ok(table),
+            None => bail!("No `{}` table named `{}`", segment, selector),
+        };
+    }
+    match item.as_table_like_mut() {
+        Some(table) => Ok(table),
+        None => bail!("`{}` is not a table", segment),
+    }
+}
+
+/// parses a value given on the command line as TOML, falling back to a plain string
+pub fn parse_value(value: &str) -> Value {
+    match value.parse::<Value>() {
+        Ok(mut parsed) => {
+            parsed.decor_mut().clear();
+            parsed
+        }
+        Err(_) => Value::from(value),
+    }
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a.as_str(), b.as_str()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.to_string().trim() == b.to_string().trim(),
+    }
+}
+
+fn format_item(item: &Item) -> String {
+    match item {
+        Item::Value(value) => {
+            let mut value = value.clone();
+            value.decor_mut().clear();
+            value.to_string()
+        }
+        Item::Table(table) => {
+            let mut inline = InlineTable::new();
+            for (key, item) in table.iter() {
+                if let Item::Value(value) = item {
+                    inline.insert(key, value.clone());
+                }
+            }
+            inline.fmt();
+            inline.to_string()
+        }
+        _ => item.to_string().trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TOML: &str = r#"# my project
+[project]
+name = "demo" # the name
+targets = ["bin"]
+
+[build]
+# all sources
+src = ["main.c"]
+include_dirs = []
+output = "demo"
+
+[custom]
+keep = true
+
+[[target]]
+name = "app"
+kind = "bin"
+"#;
+
+    fn open(contents: &str) -> (tempfile::TempDir, ConfigDocument) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("RustyForge.toml");
+        fs::write(&path, contents).unwrap();
+        let doc = ConfigDocument::open(&path).unwrap();
+        (dir, doc)
+    }
+
+    #[test]
+    fn test_add_preserves_formatting() {
+        let (dir, mut doc) = open(TOML);
+        assert!(doc.add("build.src", Value::from("lib.c")).unwrap());
+        assert!(!doc.add("build.src", Value::from("lib.c")).unwrap());
+        assert!(doc.add("build.include_dirs", Value::from("include")).unwrap());
+        doc.save().unwrap();
+
+        let written = fs::read_to_string(dir.path().join("RustyForge.toml")).unwrap();
+        let expected = TOML
+            .replace(r#"src = ["main.c"]"#, r#"src = ["main.c", "lib.c"]"#)
+            .replace("include_dirs = []", r#"include_dirs = ["include"]"#);
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_get_set_remove() {
+        let (_dir, mut doc) = open(TOML);
+        assert_eq!(doc.get("project.name").unwrap(), Some(r#""demo""#.to_string()));
+        assert_eq!(doc.get("build.missing").unwrap(), None);
+        assert_eq!(doc.get("target.app.kind").unwrap(), Some(r#""bin""#.to_string()));
+
+        doc.set("project.name", parse_value("renamed")).unwrap();
+        doc.set("build.cflags", parse_value(r#"["-O2"]"#)).unwrap();
+        doc.set("profile.bench.inherits", parse_value("release")).unwrap();
+        doc.set("target.0.output", parse_value("app_exe")).unwrap();
+        assert!(doc.remove("build.src", Some(parse_value("main.c"))).unwrap());
+        assert!(doc.remove("build.output", None).unwrap());
+        assert!(!doc.remove("build.output", None).unwrap());
+
+        let written = doc.doc.to_string();
+        assert!(written.contains(r#"name = "renamed" # the name"#));
+        assert!(written.contains(r#"cflags = ["-O2"]"#));
+        assert!(written.contains("[profile.bench]\ninherits = \"release\""));
+        assert!(!written.contains("[profile]\n"));
+        assert!(written.contains(r#"output = "app_exe""#));
+        assert!(written.contains("src = []"));
+        assert!(written.contains("[custom]\nkeep = true"));
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        let (_dir, mut doc) = open(TOML);
+        assert!(doc.set("build..src", Value::from("x")).is_err());
+        assert!(doc.set("project.name.inner", Value::from("x")).is_err());
+        assert!(doc.add("project.name", Value::from("x")).is_err());
+        assert!(doc.set("target.missing.src", Value::from("x")).is_err());
+    }
+}
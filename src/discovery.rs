@@ -1,9 +1,9 @@
-use globset::{Glob, GlobSetBuilder};
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSetBuilder};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 use std::collections::HashSet;
 use crate::arguments::DiscoverOptions;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use crate::fs_utils::{add_to_build_toml, normalize_path};
 use crate::fs_utils::BuildField::{IncludeDirs, Src};
 use crate::ui::event_file_found;
@@ -49,13 +49,74 @@ pub fn should_be_ignored(name: &String, ignore: &[String] ) -> bool {
     globset.is_match(Path::new(name))
 }
 
+/// whether a `build.src` entry is a glob pattern rather than a plain path
+pub fn is_glob(entry: &str) -> bool {
+    entry.contains(['*', '?', '[', '{'])
+}
+
+/// compiles a source pattern, `*` does not match across directories, `**` does
+pub fn source_glob(pattern: &str) -> Result<GlobMatcher> {
+    let glob = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob pattern: {}", pattern))?;
+    Ok(glob.compile_matcher())
+}
+
+/// Expands the source entries relative to `root`. Plain paths are kept as they are,
+/// glob patterns are replaced by the matching files in sorted order.
+/// Every file matching one of the `exclude` entries is removed. Patterns do not look into
+/// `forge_dir` and hidden directories, which hold build output and other tools' files.
+pub fn expand_sources(root: &Path, forge_dir: &Path, src: &[String], exclude: &[String]) -> Result<Vec<String>> {
+    let excludes = exclude.iter()
+        .map(|pattern| source_glob(pattern))
+        .collect::<Result<Vec<_>>>()?;
+    
+    let mut files = Vec::new();
+    let mut candidates: Option<Vec<String>> = None;
+    for entry in src {
+        if !is_glob(entry) {
+            files.push(normalize_path(Path::new(entry)));
+            continue;
+        }
+        let matcher = source_glob(entry)?;
+        // walk the tree once, no matter how many patterns there are
+        let candidates = candidates.get_or_insert_with(|| {
+            let forge_dir = root.join(forge_dir).canonicalize().ok();
+            let mut found: Vec<String> = WalkDir::new(root)
+                .into_iter()
+                .filter_entry(|entry| {
+                    !entry.file_type().is_dir() || entry.depth() == 0 || !(
+                        entry.file_name().to_string_lossy().starts_with('.')
+                            || forge_dir.is_some() && entry.path().canonicalize().ok() == forge_dir
+                    )
+                })
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .filter_map(|entry| entry.path().strip_prefix(root).ok().map(normalize_path))
+                .collect();
+            found.sort();
+            found
+        });
+        files.extend(candidates.iter().filter(|file| matcher.is_match(file)).cloned());
+    }
+    
+    let mut expanded: Vec<String> = Vec::new();
+    for file in files {
+        if !excludes.iter().any(|e| e.is_match(&file)) && !expanded.contains(&file) {
+            expanded.push(file);
+        }
+    }
+    Ok(expanded)
+}
+
 pub fn find_c_files(root: &str) -> Vec<PathBuf> {
     WalkDir::new(root)
         .into_iter()
         .filter_map(|entry| entry.ok())
         .filter(|entry| {
             entry.file_type().is_file()
-                && entry.path().extension().map_or(false, |ext| ext == "c")
+                && entry.path().extension().is_some_and(|ext| ext == "c")
         })
         .map(|entry| entry.into_path())
         .collect()
@@ -65,7 +126,7 @@ pub fn find_header_dirs(root: &str) -> Vec<PathBuf> {
 
     for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
         if entry.file_type().is_file()
-            && entry.path().extension().map_or(false, |ext| ext == "h")
+            && entry.path().extension().is_some_and(|ext| ext == "h")
         {
             if let Some(parent) = entry.path().parent() {
                 dirs.insert(parent.to_path_buf());
@@ -103,6 +164,37 @@ mod tests {
         assert_eq!(header_dirs.len(), 1);
         assert_eq!(header_dirs[0], h_file_path.parent().unwrap());
     }
+    
+    #[test]
+    fn test_expand_sources() {
+        let dir = tempdir().unwrap();
+        for file in [
+            "main.c", "src/a.c", "src/b.c", "src/net/c.c", "src/net/c_test.c", "src/notes.txt",
+            "forge/gen.c", "forge/.forge/probe/probe.c", ".git/hook.c",
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        
+        let flat = expand_sources(dir.path(), Path::new("forge"), &strings(&["main.c", "src/*.c"]), &[]).unwrap();
+        assert_eq!(flat, strings(&["main.c", "src/a.c", "src/b.c"]));
+        
+        let recursive = expand_sources(
+            dir.path(),
+            Path::new("forge"),
+            &strings(&["src/**/*.c", "src/a.c"]),
+            &strings(&["**/*_test.c", "src/b.c"]),
+        ).unwrap();
+        assert_eq!(recursive, strings(&["src/a.c", "src/net/c.c"]));
+        
+        assert!(expand_sources(dir.path(), Path::new("forge"), &strings(&["src/[.c"]), &[]).is_err());
+
+        // the output directory and hidden directories are not searched, plain paths are kept
+        let everything = expand_sources(dir.path(), Path::new("forge"), &strings(&["**/*.c", "forge/gen.c"]), &[]).unwrap();
+        assert_eq!(everything, strings(&["main.c", "src/a.c", "src/b.c", "src/net/c.c", "src/net/c_test.c", "forge/gen.c"]));
+    }
 
 }
 
@@ -0,0 +1,114 @@
+use std::fmt::Display;
+
+/// A failed rustyforge command, by the stage that failed.
+/// Every kind exits with its own code, so scripts and CI can tell them apart.
+#[derive(Debug)]
+pub enum ForgeError {
+    /// the RustyForge.toml is missing, invalid or could not be edited
+    Config(anyhow::Error),
+    /// a source file did not compile
+    Compile(anyhow::Error),
+    /// an executable or library could not be linked or archived
+    Link(anyhow::Error),
+    /// the forge directory or the build state could not be read or written
+    Fs(anyhow::Error),
+    /// anything else, e.g. the executable of `run` could not be started
+    Other(anyhow::Error),
+}
+
+impl ForgeError {
+    /// exit code of the process; 2 is left to clap, for invalid command line arguments
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ForgeError::Other(_) => 1,
+            ForgeError::Config(_) => 3,
+            ForgeError::Compile(_) => 4,
+            ForgeError::Link(_) => 5,
+            ForgeError::Fs(_) => 6,
+        }
+    }
+
+    /// the error of a build stage, keeping the kind of a `ForgeError` raised inside it,
+    /// e.g. a configuration problem that is only found while compiling
+    pub fn in_stage(stage: fn(anyhow::Error) -> ForgeError, e: anyhow::Error) -> ForgeError {
+        match e.downcast::<ForgeError>() {
+            Ok(e) => e,
+            Err(e) => stage(e),
+        }
+    }
+}
+
+impl Display for ForgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // `{:#}` prints the whole context chain of the error
+        match self {
+            ForgeError::Config(e) => write!(f, "configuration error: {:#}", e),
+            ForgeError::Compile(e) => write!(f, "compilation failed: {:#}", e),
+            ForgeError::Link(e) => write!(f, "linking failed: {:#}", e),
+            ForgeError::Fs(e) => write!(f, "file system error: {:#}", e),
+            ForgeError::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for ForgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ForgeError::Config(e)
+            | ForgeError::Compile(e)
+            | ForgeError::Link(e)
+            | ForgeError::Fs(e)
+            | ForgeError::Other(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ForgeError {
+    fn from(e: anyhow::Error) -> Self {
+        ForgeError::Other(e)
+    }
+}
+
+impl From<std::io::Error> for ForgeError {
+    fn from(e: std::io::Error) -> Self {
+        ForgeError::Fs(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            ForgeError::Config(anyhow!("x")),
+            ForgeError::Compile(anyhow!("x")),
+            ForgeError::Link(anyhow!("x")),
+            ForgeError::Fs(anyhow!("x")),
+            ForgeError::Other(anyhow!("x")),
+        ];
+        let mut codes: Vec<u8> = errors.iter().map(ForgeError::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&2));
+    }
+
+    #[test]
+    fn test_in_stage() {
+        let error = ForgeError::in_stage(ForgeError::Compile, anyhow!("main.c: error"));
+        assert_eq!(error.exit_code(), 4);
+        let error = ForgeError::in_stage(ForgeError::Compile, ForgeError::Config(anyhow!("bad flag")).into());
+        assert_eq!(error.exit_code(), 3);
+        assert_eq!(error.to_string(), "configuration error: bad flag");
+    }
+
+    #[test]
+    fn test_display_includes_context() {
+        let error: anyhow::Result<()> = Err(anyhow!("undefined reference to `foo`"));
+        let error = ForgeError::Link(error.context("Could not link target `app`").unwrap_err());
+        assert_eq!(error.to_string(), "linking failed: Could not link target `app`: undefined reference to `foo`");
+    }
+}
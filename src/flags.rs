@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use anyhow::{anyhow, bail, Context, Result};
+use tempfile::TempDir;
+use crate::build_state::{fingerprint, BuildState};
+use crate::config::{Config, FlagPolicy, Profile, Warnings};
+use crate::error::ForgeError;
+use crate::toolchain::{compiler_version, Toolchain};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FlagKind {
+    Compile,
+    Link,
+}
+
+/// Checks the flags of a target against `build.flag_policy`, returning the flags to use.
+/// Unless the policy is off, flags `build.warnings` or the profile already set are dropped, and every other flag
+/// is tried with the compiler once. A flag the compiler rejects fails the build in strict mode,
+/// and is dropped with a warning otherwise. The results are kept in the build state.
+/// `compiler_id` and `profile` are those of the build, so that a warm cache runs no compiler.
+pub fn check_flags(
+    config: &Config,
+    state: &mut BuildState,
+    compiler_id: &str,
+    profile: &Profile,
+    kind: FlagKind,
+    flags: &[String],
+    target: &str,
+) -> Result<Vec<String>> {
+    let policy = config.forge.build.flag_policy;
+    if policy == FlagPolicy::Off || flags.is_empty() {
+        return Ok(flags.to_vec());
+    }
+    let toolchain = config.toolchain()?;
+    let mut prober = Prober::new(toolchain.as_ref(), compiler_id);
+
+    let build = &config.forge.build;
+    let warning_flags = toolchain.warning_flags(build.warnings.unwrap_or(Warnings::Extra), build.warnings_as_errors);
+    let defines: Vec<String> = profile.defines.iter().flatten().map(|d| format!("-D{}", d)).collect();
+    let standard = build.c_standard
+        .map(|standard| toolchain.standard_flag(standard, compiler_version(compiler_id)))
+        .transpose()?;
+
+    let mut checked = Vec::new();
+    for flag in flags {
+        if kind == FlagKind::Compile {
+            if warning_flags.contains(flag) {
+                eprintln!("Warning: Flag '{}' of target `{}` is already set by `build.warnings`, it is ignored.", flag, target);
+                continue;
+            }
+            if defines.contains(flag) {
+                eprintln!("Warning: Flag '{}' of target `{}` is already set by the build profile, it is ignored.", flag, target);
+                continue;
+            }
+            if let Some(standard) = standard.as_ref().filter(|standard| flag.starts_with("-std=") && flag != *standard) {
+                eprintln!("Warning: Flag '{}' of target `{}` conflicts with `build.c_standard` ({}).", flag, target, standard);
+            }
+        }
+        if prober.accepts(state, kind, flag)? {
+            checked.push(flag.clone());
+        }
+        else if policy == FlagPolicy::Strict {
+            // a problem of the configuration, not of the sources
+            return Err(ForgeError::Config(anyhow!(
+                "Flag '{}' of target `{}` is not supported by {}", flag, target, toolchain.tools().cc
+            )).into());
+        }
+        else {
+            eprintln!("Warning: Flag '{}' of target `{}` is not supported by {}, it is ignored.", flag, target, toolchain.tools().cc);
+        }
+    }
+    Ok(checked)
+}
+
+/// tries flags on a tiny program, in a temporary directory outside the project so that
+/// no source glob picks up the probe. The directory is created for the first flag not in the cache
+struct Prober<'a> {
+    toolchain: &'a dyn Toolchain,
+    compiler_id: &'a str,
+    dir: Option<TempDir>,
+    object_compiled: bool,
+}
+
+impl<'a> Prober<'a> {
+    fn new(toolchain: &'a dyn Toolchain, compiler_id: &'a str) -> Self {
+        Prober { toolchain, compiler_id, dir: None, object_compiled: false }
+    }
+
+    /// the directory holding `probe.c`
+    fn dir(&mut self) -> Result<PathBuf> {
+        if let Some(dir) = &self.dir {
+            return Ok(dir.path().to_path_buf());
+        }
+        let dir = tempfile::Builder::new().prefix("forge-probe").tempdir()
+            .context("Could not create the flag probe directory")?;
+        std::fs::write(dir.path().join("probe.c"), "int main(void) { return 0; }\n")?;
+        Ok(self.dir.insert(dir).path().to_path_buf())
+    }
+
+    /// the command trying `flag` on the probe in `dir`
+    fn command(&self, kind: FlagKind, flag: &str, dir: &Path) -> Command {
+        let (source, object) = (dir.join("probe.c"), dir.join("probe.o"));
+        match kind {
+            FlagKind::Compile => {
+                let mut cmd = self.toolchain.compiler();
+                self.toolchain.reject_unknown_flags(&mut cmd);
+                cmd.arg(flag);
+                self.toolchain.compile_args(&mut cmd, &source, &object);
+                cmd
+            }
+            FlagKind::Link => {
+                let mut cmd = self.toolchain.linker();
+                cmd.arg(&object).arg(flag).arg("-o").arg(dir.join("probe"));
+                cmd
+            }
+        }
+    }
+
+    /// whether the compiler accepts the flag, from the build state if it was tried before
+    fn accepts(&mut self, state: &mut BuildState, kind: FlagKind, flag: &str) -> Result<bool> {
+        // libraries and their paths depend on the project, not on the compiler
+        if kind == FlagKind::Link && (flag.starts_with("-l") || flag.starts_with("-L")) {
+            return Ok(true);
+        }
+        // the temporary directory changes with every run, so it is left out of the key
+        let key = fingerprint(self.compiler_id, &self.command(kind, flag, Path::new("")), &BTreeMap::new());
+        if let Some(accepted) = state.probes.get(&key) {
+            return Ok(*accepted);
+        }
+
+        let dir = self.dir()?;
+        let dir = dir.as_path();
+        // linker flags are tried on an object of the probe
+        if kind == FlagKind::Link && !self.object_compiled {
+            let mut compile = self.toolchain.compiler();
+            self.toolchain.compile_args(&mut compile, &dir.join("probe.c"), &dir.join("probe.o"));
+            if !succeeds(&mut compile)? {
+                bail!("Could not compile the flag probe with {}", self.toolchain.tools().cc);
+            }
+            self.object_compiled = true;
+        }
+        let accepted = succeeds(&mut self.command(kind, flag, dir))?;
+        state.probes.insert(key, accepted);
+        Ok(accepted)
+    }
+}
+
+fn succeeds(cmd: &mut Command) -> Result<bool> {
+    let status = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status()
+        .with_context(|| format!("Failed to run {}", cmd.get_program().to_string_lossy()))?;
+    Ok(status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CStandard;
+    use crate::tests::dummy_config;
+
+    fn check(config: &Config, state: &mut BuildState, kind: FlagKind, flags: &[String]) -> Result<Vec<String>> {
+        let compiler_id = config.toolchain()?.identity()?;
+        check_flags(config, state, &compiler_id, &config.profile()?, kind, flags, "bin")
+    }
+
+    #[test]
+    fn test_check_flags_gcc() {
+        let mut config = dummy_config(true);
+        let mut state = BuildState::default();
+        let flags: Vec<String> = ["-funroll-loops", "-Wall", "-fno-such-flag", "-Wno-unused-parameter"]
+            .iter().map(|f| f.to_string()).collect();
+
+        config.forge.build.flag_policy = FlagPolicy::Warn;
+        let checked = check(&config, &mut state, FlagKind::Compile, &flags).unwrap();
+        assert_eq!(checked, ["-funroll-loops", "-Wno-unused-parameter"]);
+        assert_eq!(state.probes.len(), 3);
+        assert_eq!(state.probes.values().filter(|accepted| **accepted).count(), 2);
+
+        config.forge.build.flag_policy = FlagPolicy::Strict;
+        let error = check(&config, &mut state, FlagKind::Compile, &flags).unwrap_err();
+        assert!(error.to_string().contains("-fno-such-flag"));
+        // the build exits with the code of a configuration error
+        assert_eq!(ForgeError::in_stage(ForgeError::Compile, error).exit_code(), 3);
+        // the results were cached
+        assert_eq!(state.probes.len(), 3);
+
+        config.forge.build.flag_policy = FlagPolicy::Off;
+        assert_eq!(check(&config, &mut state, FlagKind::Compile, &flags).unwrap(), flags);
+
+        let flags: Vec<String> = ["-lm", "-Wl,--as-needed", "-Wl,--no-such-option"].iter().map(|f| f.to_string()).collect();
+        config.forge.build.flag_policy = FlagPolicy::Warn;
+        let checked = check(&config, &mut state, FlagKind::Link, &flags).unwrap();
+        assert_eq!(checked, ["-lm", "-Wl,--as-needed"]);
+    }
+
+    #[test]
+    fn test_check_flags_set_by_the_build() {
+        let mut config = dummy_config(true);
+        let mut state = BuildState::default();
+        let flags: Vec<String> = ["-Wall", "-Wextra", "-DDEBUG", "-DVERBOSE", "-std=c99"].iter().map(|f| f.to_string()).collect();
+
+        // the default warning level and the debug profile already set these
+        let checked = check(&config, &mut state, FlagKind::Compile, &flags).unwrap();
+        assert_eq!(checked, ["-DVERBOSE", "-std=c99"]);
+
+        config.forge.build.warnings = Some(Warnings::All);
+        let checked = check(&config, &mut state, FlagKind::Compile, &flags).unwrap();
+        assert_eq!(checked, ["-Wextra", "-DVERBOSE", "-std=c99"]);
+
+        // a conflicting standard is only warned about
+        config.forge.build.warnings = Some(Warnings::None);
+        config.forge.build.c_standard = Some(CStandard::C11);
+        let checked = check(&config, &mut state, FlagKind::Compile, &flags).unwrap();
+        assert_eq!(checked, ["-Wall", "-Wextra", "-DVERBOSE", "-std=c99"]);
+    }
+
+    #[test]
+    fn test_probe_common_flags() {
+        let config = dummy_config(true);
+        let toolchain = config.toolchain().unwrap();
+        let compiler_id = toolchain.identity().unwrap();
+        let mut prober = Prober::new(toolchain.as_ref(), &compiler_id);
+        let mut state = BuildState::default();
+
+        for flag in ["-O2", "-g", "-std=c99", "-fvisibility=hidden", "-DDEBUG_MODE", "-I/usr/include", "-pipe", "-flto"] {
+            assert!(prober.accepts(&mut state, FlagKind::Compile, flag).unwrap(), "Expected '{}' to be accepted", flag);
+        }
+        for flag in ["-Wbanana", "--weirdflag"] {
+            assert!(!prober.accepts(&mut state, FlagKind::Compile, flag).unwrap(), "Expected '{}' to be rejected", flag);
+        }
+        for flag in ["-pthread", "-rdynamic", "-Wl,--as-needed", "-lssl", "-L/no/such/dir"] {
+            assert!(prober.accepts(&mut state, FlagKind::Link, flag).unwrap(), "Expected '{}' to be accepted", flag);
+        }
+        for flag in ["-Zweird", "-Wl,--weirdflag"] {
+            assert!(!prober.accepts(&mut state, FlagKind::Link, flag).unwrap(), "Expected '{}' to be rejected", flag);
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use std::fs;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use crate::config::{Build, Config, Forge, Project};
-use crate::hashes::HashCache;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use toml_edit::{Array, Value};
+use crate::config::{Config, Target};
+use crate::config_edit::ConfigDocument;
+use walkdir::WalkDir;
 use anyhow::{Result, bail};
-use crate::arguments::{InitOptions, Command::*, Command};
+use crate::arguments::{profile_name, InitOptions, Command::*, Command};
 
 pub fn create_forge_dir() -> Result<()> {
     let dir_path = Path::new("forge");
@@ -17,7 +19,7 @@ pub fn create_forge_dir() -> Result<()> {
 #[derive(Debug, PartialEq)]
 pub enum FileError {
     FileNotFound(String),
-    FileError(String),
+    IoError(String),
     CwdError(String),
 }
 
@@ -25,7 +27,7 @@ impl std::fmt::Display for FileError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FileError::FileNotFound(msg) => write!(f, "File not found: {}", msg),
-            FileError::FileError(msg) => write!(f, "File error: {}", msg),
+            FileError::IoError(msg) => write!(f, "File error: {}", msg),
             FileError::CwdError(msg) => write!(f, "CWD error: {}", msg),
         }
     }
@@ -36,7 +38,7 @@ pub fn find_file(filename: &str) -> Result<PathBuf, FileError> {
     let cwd = std::env::current_dir().map_err(|e| FileError::CwdError(format!("CWD Error: {}", e)))?;
     let full_path = cwd.join(filename);
     if full_path.exists() && full_path.is_file() {
-        full_path.canonicalize().map_err(|e| FileError::FileError(format!("File Error: {}", e)))?;
+        full_path.canonicalize().map_err(|e| FileError::IoError(format!("File Error: {}", e)))?;
         let normalized_path = normalize_path(&full_path);
         Ok(PathBuf::from(normalized_path))
     } else {
@@ -44,95 +46,135 @@ pub fn find_file(filename: &str) -> Result<PathBuf, FileError> {
     }
 }
 
-pub fn get_equivalent_forge_path(input_path: &Path, config: &Config, shared: bool) -> Result<PathBuf> {
+/// Path of the object file for a source file of the target.
+/// The object mirrors the directory of the source relative to the project,
+/// so `src/net/util.c` and `src/fs/util.c` do not collide.
+pub fn get_equivalent_forge_path(input_path: &Path, config: &Config, target: &Target) -> Result<PathBuf> {
     let cwd = std::env::current_dir()?;
     let file_stem = input_path.file_stem().and_then(|s| s.to_str())
         .ok_or(anyhow::anyhow!("Failed to get file stem"))?;
     
-    let forge_path: PathBuf;
-    if shared { 
-        forge_path = cwd.join("forge").join("libs").join("obj")
-            .join(format!("{}.o", file_stem));
-    }
-    else { 
-        forge_path = match &config.args.command { 
-            Run(opt) => {
-                let build_type = match opt.release {
-                    true => "release",
-                    false => "debug",
-                };
-                cwd.join("forge").join(build_type).join(format!("{}.o", file_stem))
-            }
-            Build(opt) => {
-                let build_type = match opt.release { 
-                    true => "release",
-                    false => "debug",
-                };
-                cwd.join("forge").join(build_type).join(format!("{}.o", file_stem))
-            }
-            Rebuild(opt) => {
-                let build_type = match opt.release { 
-                    true => "release",
-                    false => "debug",
-                };
-                cwd.join("forge").join(build_type).join(format!("{}.o", file_stem))
-            }
-            _ => {
-                bail!("Invalid command")
+    match &config.args.command { 
+        Run(_) | Debug(_) | Build(_) | Rebuild(_) => {
+            let relative = input_path.strip_prefix(&cwd).unwrap_or(input_path);
+            let mut obj_path = cwd.join(target_obj_dir(config, target));
+            if let Some(parent) = relative.parent() {
+                obj_path.push(mirror_dir(parent));
             }
+            Ok(obj_path.join(format!("{}.o", file_stem)))
+        }
+        _ => {
+            bail!("Invalid command")
         }
     }
-    Ok(forge_path)
+}
+
+/// the directory below the object directory, that mirrors the directory of a source file.
+/// `..` becomes `__parent__` and the root `__root__`, so sources outside the project stay
+/// inside the object directory
+fn mirror_dir(dir: &Path) -> PathBuf {
+    dir.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            Component::ParentDir => Some(OsStr::new("__parent__")),
+            Component::RootDir => Some(OsStr::new("__root__")),
+            _ => None,
+        })
+        .collect()
+}
+
+/// the object files produced by the sources of a target, in the order of the sources.
+/// Two sources sharing an object, e.g. `../lib/x.c` and `__parent__/lib/x.c`, are an error
+pub fn target_objects(config: &Config, target: &Target, sources: &[String]) -> Result<Vec<PathBuf>> {
+    let mut objects: Vec<PathBuf> = Vec::new();
+    for source in sources {
+        let object = get_equivalent_forge_path(Path::new(source), config, target)?;
+        if let Some(j) = objects.iter().position(|o| *o == object) {
+            bail!("Sources `{}` and `{}` of target `{}` compile to the same object {}", sources[j], source, target.name, object.display());
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+fn manifest_path(obj_dir: &Path) -> PathBuf {
+    obj_dir.join("manifest.json")
+}
+
+/// records the objects of a target, relative to its object directory
+pub fn save_object_manifest(obj_dir: &Path, objects: &[PathBuf]) -> Result<()> {
+    let relative: Vec<String> = objects.iter()
+        .map(|o| normalize_path(o.strip_prefix(obj_dir).unwrap_or(o)))
+        .collect();
+    fs::write(manifest_path(obj_dir), serde_json::to_string_pretty(&relative)?)?;
+    Ok(())
+}
+
+/// the objects recorded for a target by the last compile, in link order
+pub fn load_object_manifest(obj_dir: &Path) -> Result<Vec<PathBuf>> {
+    let path = manifest_path(obj_dir);
+    if !path.exists() {
+        bail!("No object manifest found at {}, the target has not been compiled", path.display());
+    }
+    let relative: Vec<String> = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    Ok(relative.iter().map(|o| obj_dir.join(o)).collect())
+}
+
+/// removes the objects in the object directory, that are not listed in the manifest,
+/// e.g. objects of sources removed from the target. Returns the removed objects
+pub fn prune_orphaned_objects(obj_dir: &Path, objects: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for o_file in find_o_files(obj_dir) {
+        if !objects.contains(&o_file) {
+            fs::remove_file(&o_file)?;
+            removed.push(o_file);
+        }
+    }
+    Ok(removed)
 }
 
 pub fn normalize_path(path: &Path) -> String {
     let path = path.strip_prefix("./").unwrap_or(path);
     let s = path.to_string_lossy();
-    if s.starts_with(r"\\?\") {
-        s[4..].replace("\\", "/")
-    } else {
-        s.replace("\\", "/")
+    match s.strip_prefix(r"\\?\") {
+        Some(stripped) => stripped.replace("\\", "/"),
+        None => s.replace("\\", "/"),
     }
 }
 
 
-pub fn create_forge_sub_dir(name: &str) -> Result<()> {
-    let dir_path = Path::new("forge").join(name);
+pub fn create_forge_sub_dir(forge_dir: &Path, name: &str) -> Result<()> {
+    let dir_path = forge_dir.join(name);
     if !dir_path.exists() {
         fs::create_dir_all(dir_path)?;
     }
     Ok(())   
 }
 
-// allow dead code because this is only used on linux and macOS
-#[allow(dead_code)]
+/// the library paths holding shared libraries the executable links against
 pub fn find_r_paths(config: &Config) -> Vec<PathBuf> {
     let mut paths: Vec<PathBuf> = Vec::new();
     // only check if dependencies are set
-    match &config.forge.dependencies {
-        Some(deps) => {
-            // check for all paths
-            for path in &deps.library_paths {
-                // check for all libraries
-                for lib in &deps.libraries {
-                    // check if .so exists or lib.so exists
-                    let full_path = Path::new(path).join(format!("{}.so", lib));
-                    let alt_full_path = Path::new(path).join(format!("lib{}.so", lib));
-                    
-                    if full_path.exists() && !paths.contains(&PathBuf::from(path)) {
-                        let normalized_path = normalize_path(Path::new(path));
-                        paths.push(PathBuf::from(normalized_path));
-                        break;   
-                    }
-                    else if alt_full_path.exists() && !paths.contains(&PathBuf::from(path)) {
-                        let normalized_path = normalize_path(Path::new(path));
-                        paths.push(PathBuf::from(normalized_path));   
-                        break;   
-                    }
+    if let Some(deps) = &config.forge.dependencies {
+        let mut lib_paths: Vec<PathBuf> = deps.library_paths.iter().map(PathBuf::from).collect();
+        // libraries built by the project and by other workspace members
+        lib_paths.push(libs_out_dir(config));
+        // check for all paths
+        for path in &lib_paths {
+            // check for all libraries
+            for lib in &deps.libraries {
+                // check if the shared library exists, with or without the lib prefix
+                let shared_name = config.platform().shared_lib_name(lib);
+                let full_path = path.join(shared_name.strip_prefix("lib").unwrap_or(&shared_name));
+                let alt_full_path = path.join(&shared_name);
+                
+                if (full_path.exists() || alt_full_path.exists()) && !paths.contains(path) {
+                    let normalized_path = normalize_path(path);
+                    paths.push(PathBuf::from(normalized_path));
+                    break;   
                 }
             }
         }
-        None => {}
     }
     paths
 }
@@ -142,43 +184,6 @@ pub fn std_toml_path() -> Result<PathBuf> {
         .join("RustyForge.toml"))
 }
 
-pub fn std_hash_cache_path() -> Result<PathBuf> {
-    Ok(std::env::current_dir()?
-        .join("forge")
-        .join(".forge")
-        .join("hash_cache.json"))
-}
-
-pub fn init_hash_cache_json(json_path: PathBuf) -> Result<()> {
-    if !json_path.exists() {
-        if let Some(parent) = json_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        fs::write(&json_path, "[]")?;
-    }
-
-    Ok(())
-}
-
-
-pub fn load_hash_cache_json(json_path: PathBuf) -> Result<Vec<HashCache>, Box<dyn std::error::Error>> {
-    let data = if Path::new(&json_path).exists() {
-        fs::read_to_string(json_path)?
-    }
-    else { 
-        "[]".to_string()
-    };
-    
-    let entries: Vec<HashCache> = serde_json::from_str(&data)?;
-    
-    Ok(entries)  
-}
-
-pub fn save_hash_cache_json(entries: &Vec<HashCache>, json_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    let data = serde_json::to_string_pretty(entries)?;
-    fs::write(json_path, data)?;
-    Ok(())
-}
 pub fn init_default_toml(opt: &InitOptions) -> Result<()> {
     let cwd = std::env::current_dir()?;
 
@@ -192,34 +197,16 @@ pub fn init_default_toml(opt: &InitOptions) -> Result<()> {
         None => &"gcc".to_string(),
     };
     
-    let default = Forge {
-        project: Project {
-            name: dir_name.to_string(),
-            targets: vec!["bin".to_string()],
-        },
-        build: Build {
-            compiler: Some(compiler.to_string()),
-            src: vec![],
-            include_dirs: vec![],
-            output: dir_name.to_string(),
-            cflags: None,
-            ldflags: None,
-        },
-        dependencies: None,
-    };
-
-    let toml_string = toml::to_string_pretty(&default)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
     let path = cwd.join("RustyForge.toml");
 
-    if path.exists() {
-        fs::remove_file(&path)?; 
-    }
-
-    let mut file = fs::File::create(&path)?;
-    file.write_all(toml_string.as_bytes())?;
-    file.sync_all()?; 
+    let mut doc = ConfigDocument::new(&path);
+    doc.set("project.name", Value::from(dir_name))?;
+    doc.set("project.targets", Value::from_iter(["bin"]))?;
+    doc.set("build.compiler", Value::from(compiler.as_str()))?;
+    doc.set("build.src", Value::Array(Array::new()))?;
+    doc.set("build.include_dirs", Value::Array(Array::new()))?;
+    doc.set("build.output", Value::from(dir_name))?;
+    doc.save()?;
 
     println!("Created default RustyForge.toml at {}", path.display());
     Ok(())
@@ -230,13 +217,11 @@ pub fn ensure_necessary_files() -> Result<()> {
     let cwd = std::env::current_dir()?; // current working directory
     let forge_dir = cwd.join("forge"); // general forge directory
     let forge_dir_hidden = forge_dir.join(".forge"); // hidden forge directory
-    let hash_cache_path = forge_dir_hidden.join("hash_cache.json");
     let toml_path = cwd.join("RustyForge.toml");
     
     let required_paths = [
         &forge_dir,
         &forge_dir_hidden,
-        &hash_cache_path,
         &toml_path,
     ];
     // check if all required files exist
@@ -252,9 +237,8 @@ pub fn ensure_necessary_files() -> Result<()> {
 pub fn init_forge_structure(opt: &InitOptions) -> Result<()> {
     // create forge files
     create_forge_dir()?;
-    create_forge_sub_dir(".forge")?;
-    init_hash_cache_json(std_hash_cache_path()?)?;
-    init_default_toml(&opt)?;
+    create_forge_sub_dir(Path::new("forge"), ".forge")?;
+    init_default_toml(opt)?;
     // create the default project structure
     fs::create_dir_all("src")?;
     fs::create_dir_all("include")?;
@@ -267,103 +251,60 @@ pub enum BuildField {
 }
 
 pub fn add_to_build_toml(toml_path: &Path, field: BuildField, value: String) -> Result<()> {
-    
-    let contents = fs::read_to_string(&toml_path)?;
-    let mut forge = toml::from_str::<Forge>(&contents)?;
-    let vec_ref = match field {
-        BuildField::Src => &mut forge.build.src,
-        BuildField::IncludeDirs => &mut forge.build.include_dirs,
+    let key_path = match field {
+        BuildField::Src => "build.src",
+        BuildField::IncludeDirs => "build.include_dirs",
     };
-    
-    if !vec_ref.contains(&value) {
-        vec_ref.push(value);
+    let mut doc = ConfigDocument::open(toml_path)?;
+    if doc.add(key_path, Value::from(value))? {
+        doc.save()?;
     }
-    let updated = toml::to_string_pretty(&forge)?;
-    fs::write(&toml_path, updated)?;
     Ok(()) 
 }
 
+/// all object files below the directory, in a stable order
 pub fn find_o_files(rel_path: &Path) -> Vec<PathBuf>{
     let cwd = std::env::current_dir().expect("Failed to get current directory");
     let cwd = cwd.join(rel_path);
-
-    let mut o_files = Vec::new();
-
-    for entry in fs::read_dir(cwd).expect("Failed to read forge/ directory") {
-        let entry = entry.expect("Failed to read entry");
-        let path = entry.path();
-
-        if path.is_file() {
-            if let Some(ext) = path.extension() {
-                if ext == "o" {
-                    let norm_abs_path = find_file(path.to_str().unwrap()).unwrap();
-                    o_files.push(norm_abs_path);
-                }
-            }
-        }
+    if !cwd.is_dir() {
+        panic!("Failed to read forge/ directory");
     }
+
+    let mut o_files: Vec<PathBuf> = WalkDir::new(cwd)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "o")
+        })
+        .map(|entry| PathBuf::from(normalize_path(entry.path())))
+        .collect();
+    o_files.dedup();
     o_files
 }
 
 pub fn find_o_files_dir(cfg: &Config) -> PathBuf {
-    match &cfg.args.command {
-        Run(opt) => {
-            if opt.debug {
-                PathBuf::from("forge/debug")
-            }
-            else {
-                PathBuf::from("forge/release")
-            }
-        }
-        Build(opt) => {
-            if opt.debug {
-                PathBuf::from("forge/debug")
-            }
-            else {
-                PathBuf::from("forge/release")
-            }
-        }
-        Rebuild(opt) => {
-            if opt.debug {
-                PathBuf::from("forge/debug")
-            }
-            else {
-                PathBuf::from("forge/release")
-            }
-        }
-        _ => {
-            PathBuf::from("forge/debug")
-        }
+    cfg.output_dir().join(cfg.profile_name())
+}
+
+/// directory holding the object files of a single target
+pub fn target_obj_dir(cfg: &Config, target: &Target) -> PathBuf {
+    let obj_dir = find_o_files_dir(cfg).join("obj");
+    // workspace members share one output directory
+    match &cfg.member {
+        Some(member) => obj_dir.join(member).join(&target.name),
+        None => obj_dir.join(&target.name),
     }
 }
 
-pub fn create_build_dir(cmd: &Command) -> Result<()> {
-    match cmd { 
-        Run(opt) => {
-            if opt.debug {
-                create_forge_sub_dir("debug")?;
-            }
-            else {
-                create_forge_sub_dir("release")?;
-            }
-        },
-        Build(opt) => {
-            if opt.debug {
-                create_forge_sub_dir("debug")?;
-            }
-            else {
-                create_forge_sub_dir("release")?;
-            }
-        },
-        Rebuild(opt) => {
-            if opt.debug {
-                create_forge_sub_dir("debug")?;
-            }
-            else {
-                create_forge_sub_dir("release")?;
-            }
-        },
-        _ => {}
+/// directory holding the built libraries of the selected profile
+pub fn libs_out_dir(cfg: &Config) -> PathBuf {
+    find_o_files_dir(cfg).join("libs")
+}
+
+pub fn create_build_dir(forge_dir: &Path, cmd: &Command) -> Result<()> {
+    if let Run(_) | Debug(_) | Build(_) | Rebuild(_) = cmd {
+        create_forge_sub_dir(forge_dir, &profile_name(cmd))?;
     }
     Ok(())
 }
@@ -376,7 +317,8 @@ mod tests {
     use tempfile::tempdir;
     use std::path::Path;
     use std::path::PathBuf;
-    use crate::tests::dummy_config;
+    use crate::config::TargetKind;
+    use crate::tests::{dummy_config, dummy_target, CwdGuard};
     #[test]
     fn test_find_file_exists() {
         let dir = tempdir().expect("Failed to create temp dir");
@@ -385,19 +327,17 @@ mod tests {
         let mut file = File::create(&file_path).expect("Failed to create file");
         writeln!(file, "Hello, world!").expect("Failed to write to file");
         
-        let orig_cwd = std::env::current_dir().expect("Failed to get current directory");
-        std::env::set_current_dir(dir.path()).expect("Failed to set current directory");
+        let _cwd = CwdGuard::enter(dir.path());
         
         let result = find_file("test.txt");
         assert!(result.is_ok());
         let found_path = result.unwrap();
         assert_eq!(found_path, file_path);
         
-        std::env::set_current_dir(orig_cwd).expect("Failed to reset current directory");
     }
     #[test]
     fn test_find_file_not_exists() {
-         let orig_cwd = std::env::current_dir().unwrap();
+        let _cwd = CwdGuard::hold();
 
         let result = find_file("file_that_does_not_exist.txt");
         assert!(result.is_err());
@@ -407,7 +347,6 @@ mod tests {
             panic!("Expected FileNotFound error");
         }
 
-        std::env::set_current_dir(orig_cwd).expect("Failed to reset CWD");
     }
     
     #[test]
@@ -423,8 +362,7 @@ mod tests {
         let other_file = dir.path().join("other_file.txt");
         File::create(&other_file).expect("Failed to create other_file");
         
-        let orig_cwd = std::env::current_dir().expect("Failed to get current directory");
-        std::env::set_current_dir(dir.path()).expect("Failed to set current directory");
+        let _cwd = CwdGuard::enter(dir.path());
         
         let result = find_o_files(Path::new("."));
         assert_eq!(result.len(), 2);
@@ -432,11 +370,33 @@ mod tests {
         assert!(result.contains(&PathBuf::from(normalize_path(&file2))));
         assert!(!result.contains(&PathBuf::from(normalize_path(&other_file))));
         
-        std::env::set_current_dir(orig_cwd).expect("Failed to reset CWD");
     }
+    #[test]
+    fn test_object_manifest_and_pruning() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let obj_dir = dir.path();
+        fs::create_dir_all(obj_dir.join("src/net")).unwrap();
+        let kept = vec![obj_dir.join("src/net/util.o"), obj_dir.join("main.o")];
+        let orphan = obj_dir.join("src/removed.o");
+        for o_file in kept.iter().chain([&orphan]) {
+            File::create(o_file).unwrap();
+        }
+        
+        save_object_manifest(obj_dir, &kept).unwrap();
+        assert_eq!(load_object_manifest(obj_dir).unwrap(), kept);
+        
+        let removed = prune_orphaned_objects(obj_dir, &kept).unwrap();
+        assert_eq!(removed, vec![PathBuf::from(normalize_path(&orphan))]);
+        assert!(!orphan.exists());
+        assert!(kept.iter().all(|o| o.exists()));
+        
+        assert!(load_object_manifest(&obj_dir.join("missing")).is_err());
+    }
+    
     #[test]
     #[should_panic(expected = "Failed to read forge/ directory")]
     fn test_find_o_files_missing_dir(){
+        let _cwd = CwdGuard::hold();
         find_o_files(Path::new("missing_dir"));
     }
     
@@ -468,28 +428,66 @@ mod tests {
     
     #[test]
     fn test_get_forge_path_debug() {
+        let _cwd = CwdGuard::hold();
         let config = dummy_config(true);
         let input = PathBuf::from("src/main.c");
-        let result = get_equivalent_forge_path(&input, &config, false).unwrap();
-        let expected = std::env::current_dir().unwrap().join("forge/debug/main.o");
+        let target = dummy_target("dummy", TargetKind::Bin);
+        let result = get_equivalent_forge_path(&input, &config, &target).unwrap();
+        let expected = std::env::current_dir().unwrap().join("forge/debug/obj/dummy/src/main.o");
         assert_eq!(result, expected);
     }
 
     #[test]
     fn test_get_forge_path_release() {
+        let _cwd = CwdGuard::hold();
         let config = dummy_config(false);
         let input = PathBuf::from("src/utils.c");
-        let result = get_equivalent_forge_path(&input, &config, false).unwrap();
-        let expected = std::env::current_dir().unwrap().join("forge/release/utils.o");
+        let target = dummy_target("dummy", TargetKind::Bin);
+        let result = get_equivalent_forge_path(&input, &config, &target).unwrap();
+        let expected = std::env::current_dir().unwrap().join("forge/release/obj/dummy/src/utils.o");
         assert_eq!(result, expected);
     }
 
     #[test]
     fn test_get_forge_path_shared() {
+        let _cwd = CwdGuard::hold();
         let config = dummy_config(true); 
         let input = PathBuf::from("src/libmath.c");
-        let result = get_equivalent_forge_path(&input, &config, true).unwrap();
-        let expected = std::env::current_dir().unwrap().join("forge/libs/obj/libmath.o");
+        let target = dummy_target("math", TargetKind::Shared);
+        let result = get_equivalent_forge_path(&input, &config, &target).unwrap();
+        let expected = std::env::current_dir().unwrap().join("forge/debug/obj/math/src/libmath.o");
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_libs_out_dir_per_profile() {
+        assert_eq!(libs_out_dir(&dummy_config(true)), Path::new("forge/debug/libs"));
+        assert_eq!(libs_out_dir(&dummy_config(false)), Path::new("forge/release/libs"));
+    }
+
+    #[test]
+    fn test_get_forge_path_mirrors_source_tree() {
+        let _cwd = CwdGuard::hold();
+        let config = dummy_config(true);
+        let target = dummy_target("dummy", TargetKind::Bin);
+        let cwd = std::env::current_dir().unwrap();
+        let net = get_equivalent_forge_path(&cwd.join("src/net/util.c"), &config, &target).unwrap();
+        let fs = get_equivalent_forge_path(Path::new("src/fs/util.c"), &config, &target).unwrap();
+        let outside = get_equivalent_forge_path(Path::new("../core/util.c"), &config, &target).unwrap();
+        let obj_dir = std::env::current_dir().unwrap().join("forge/debug/obj/dummy");
+        assert_eq!(net, obj_dir.join("src/net/util.o"));
+        assert_eq!(fs, obj_dir.join("src/fs/util.o"));
+        assert_eq!(outside, obj_dir.join("__parent__/core/util.o"));
+    }
+
+    #[test]
+    fn test_target_objects_collide() {
+        let _cwd = CwdGuard::hold();
+        let config = dummy_config(true);
+        let target = dummy_target("dummy", TargetKind::Bin);
+        let sources = ["../lib/x.c".to_string(), "__parent__/lib/x.c".to_string()];
+        let error = target_objects(&config, &target, &sources).unwrap_err();
+        assert!(error.to_string().contains("compile to the same object"));
+        assert_eq!(target_objects(&config, &target, &sources[..1]).unwrap().len(), 1);
+    }
 }
@@ -1,208 +1,263 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use crate::config::{CompilerKind, Config};
-use crate::utils::{format_lib_name, is_valid_ldflag, format_shared_lib_name};
+use crate::build_state::{fingerprint, BuildState, OutputState, StateStore};
+use crate::config::{CompilerKind, Config, Profile, Target, TargetKind};
+use crate::platform::Platform;
+use crate::flags::{check_flags, FlagKind};
+use crate::utils::add_profile_ldflags;
 use crate::ui::{print_forging, verbose_command, verbose_command_hard};
-use anyhow::{bail, Result};
-use crate::compile::get_compiler_cmd;
-use crate::fs_utils::{create_forge_sub_dir, normalize_path, find_o_files, find_o_files_dir};
-
-
-#[allow(unused_imports)] // is imported for linux and macOS
-use crate::fs_utils::{find_file, find_r_paths};
+use anyhow::{bail, Context, Result};
+use crate::fs_utils::{create_forge_sub_dir, find_r_paths, normalize_path, find_o_files_dir, libs_out_dir, load_object_manifest, target_obj_dir};
 
 pub fn link(config: &Config) -> Result<()>{
     if config.compiler == CompilerKind::MSVC {
         bail!("MSVC is not supported yet");
     }
+    let mut store = StateStore::open(&config.build_state_path())?;
+    let targets = config.forge.resolve_targets()?;
+    let profile = config.profile()?;
+    // the compiler only has to identify itself when there are flags to check
+    let compiler_id = match targets.iter().any(|t| t.ldflags.as_ref().is_some_and(|f| !f.is_empty())) {
+        true => config.toolchain()?.identity()?,
+        false => String::new(),
+    };
     // clang / gcc is handled by the link() function
     // check all targets
-    for target in &config.forge.project.targets {
-        match target.as_str() {
-            "bin" => {
-                if let Err(e) = link_executable(config){
-                    eprintln!("Error: {}", e);
-                }
-            }
-            "static" => {
-                if let Err(e) = archive_static_library(config){
-                    eprintln!("Error: {}", e);
-                }
-            }
-            "shared" => {
-                if let Err(e) = link_shared_library(config){
-                    eprintln!("Error: {}", e);
-                }
-            }
-            _ => {
-                bail!("Unknown target: {} None of [bin, static, shared]", target);
-            }
+    for target in &targets {
+        let result = match target.kind {
+            TargetKind::Bin => link_executable(config, target, &profile, &compiler_id, &mut store.state),
+            TargetKind::Static => archive_static_library(config, target, &mut store.state),
+            TargetKind::Shared => link_shared_library(config, target, &profile, &compiler_id, &mut store.state),
+        };
+        if let Err(e) = result {
+            // keep what was linked so far, it does not need to be linked again
+            store.save()?;
+            return Err(e.context(format!("Could not link target `{}`", target.name)));
         }
     };
-    Ok(())
+    store.save()
 }
 
-pub fn link_shared_library(cfg: &Config) -> Result<()>{
-    let lib_name = cfg.forge.build.output.clone();
-    let mut formatted_name = lib_name.clone();
-    format_shared_lib_name(&mut formatted_name);
-    let out = format!("forge/libs/out/{}", formatted_name);
-    
-    create_forge_sub_dir("libs/out")?;
-    
-    let mut cmd= match get_compiler_cmd(&cfg) {
-        Ok(cmd) => cmd,
-        Err(e) => bail!("Failed to get compiler command: {}", e)
-    };
-    
-    let o_path = Path::new("forge/libs/obj");
-    let o_files = find_o_files(o_path);
-    
-    cmd.arg("-shared");
-    #[cfg(target_os = "linux")]
-    cmd.arg("-fPIC");
-    cmd.arg("-o").arg(out);
-    for o_file in &o_files {
-        cmd.arg(o_file);
+/// Runs a link or archive step, unless its output exists and neither the command line
+/// nor the contents of any input changed since the output was produced.
+fn run_linker(cfg: &Config, state: &mut BuildState, cmd: &mut Command, name: &str, output: &Path, inputs: &[PathBuf]) -> Result<()> {
+    let hashes = inputs.iter()
+        .map(|input| (normalize_path(input), state.hash_file(input)))
+        .collect();
+    let fingerprint = fingerprint("", cmd, &hashes);
+    if output.exists() && state.output(output).is_some_and(|o| o.fingerprint == fingerprint) {
+        if cfg.args.verbose || cfg.args.verbose_hard {
+            println!("{} is up to date", name);
+        }
+        return Ok(());
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        let windows_arg = format!("-Wl,--out-implib,forge/libs/out/lib{}.dll.a", lib_name);
-        cmd.arg(windows_arg);
-    }
-    
-    print_forging(&lib_name);
+    print_forging(name);
     if cfg.args.verbose {
-        verbose_command(&cmd);
+        verbose_command(cmd);
     }
-    else if cfg.args.verbose_hard { 
-        verbose_command_hard(&cmd);
+    else if cfg.args.verbose_hard {
+        verbose_command_hard(cmd);
     }
-    
-    let output = cmd.output().expect("Failed to run gcc");
-    
-    if !output.status.success() {
-        bail!("Hammer to rusty, linker failed: {}", String::from_utf8_lossy(&output.stderr))
+
+    // ar adds to an existing archive, which would keep the objects of removed sources
+    if output.exists() {
+        std::fs::remove_file(output)?;
+    }
+    let result = cmd.output().with_context(|| format!("Failed to run {}", cmd.get_program().to_string_lossy()))?;
+
+    if !result.status.success() {
+        bail!("Hammer to rusty, linker failed: {}", String::from_utf8_lossy(&result.stderr))
     }
-    else { 
+    else {
         println!("Forging successful!")
     }
+    state.record_output(output, OutputState { hashes, fingerprint });
     Ok(())
 }
 
-pub fn archive_static_library(cfg: &Config) -> Result<()>{
-    // get a formatted name for the library, based on the output name, and the OS(Toolchain)
-    let mut name = cfg.forge.build.output.clone();
-    format_lib_name(&mut name);
-    name = format!("forge/libs/out/{}", name);
+/// the `dependencies.libraries` and `dependencies.posix_libraries` linked into a target,
+/// a library target does not link against itself
+fn dependency_libraries<'a>(config: &'a Config, target: &Target) -> Vec<&'a String> {
+    let Some(dependencies) = &config.forge.dependencies else {
+        return Vec::new();
+    };
+    dependencies.libraries.iter()
+        .chain(&dependencies.posix_libraries)
+        .filter(|lib| target.kind == TargetKind::Bin || lib.as_str() != target.output_name())
+        .collect()
+}
+
+/// the files of the dependency libraries found in the library paths,
+/// libraries of the system are not tracked
+fn external_libraries(config: &Config, target: &Target) -> Vec<PathBuf> {
+    let Some(dependencies) = &config.forge.dependencies else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = dependencies.library_paths.iter().map(PathBuf::from).collect();
+    dirs.push(libs_out_dir(config));
+    let mut found = Vec::new();
+    for lib in dependency_libraries(config, target) {
+        let candidates = [
+            format!("lib{}.a", lib),
+            format!("lib{}.so", lib),
+            format!("lib{}.dylib", lib),
+            format!("lib{}.dll.a", lib),
+            format!("{}.dll", lib),
+            format!("{}.lib", lib),
+        ];
+        for dir in &dirs {
+            found.extend(candidates.iter().map(|c| dir.join(c)).filter(|p| p.is_file()));
+        }
+    }
+    found
+}
+
+/// adds the library paths, rpaths and libraries of `[dependencies]`, after the objects
+fn add_dependencies(config: &Config, target: &Target, cmd: &mut Command) {
+    let Some(dependencies) = &config.forge.dependencies else {
+        return;
+    };
+    for lib_path in &dependencies.library_paths {
+        cmd.arg(format!("-L{}", lib_path));
+    }
+    // libraries built by the project and by other workspace members
+    cmd.arg(format!("-L{}", libs_out_dir(config).display()));
+
+    // add all rpaths (only linux and macOS)
+    if config.platform().has_rpath() {
+        for path in find_r_paths(config) {
+            cmd.arg(format!("-Wl,-rpath={}", path.to_str().unwrap()));
+        }
+    }
+
+    // add all libraries
+    for lib in dependency_libraries(config, target) {
+        cmd.arg(format!("-l{}", lib));
+    }
+}
+
+pub fn link_shared_library(cfg: &Config, target: &Target, profile: &Profile, compiler_id: &str, state: &mut BuildState) -> Result<()>{
+    let platform = cfg.platform();
+    let lib_name = target.output_name().to_string();
+    let out = libs_out_dir(cfg).join(platform.shared_lib_name(&lib_name));
+    
+    create_forge_sub_dir(&find_o_files_dir(cfg), "libs")?;
     
-    create_forge_sub_dir("libs/out")?;
+    let mut cmd = cfg.toolchain()?.linker();
     
-    let mut cmd = Command::new("ar");
-    cmd.arg("rcs").arg(&name);
+    let o_files = load_object_manifest(&target_obj_dir(cfg, target))?;
     
-    let o_path = find_o_files_dir(&cfg); 
-    let o_files = find_o_files(&o_path);
+    cmd.arg("-shared");
+    if platform == Platform::Linux {
+        cmd.arg("-fPIC");
+    }
+    cmd.arg("-o").arg(&out);
     for o_file in &o_files {
-        // add the normalized path
-        cmd.arg(normalize_path(o_file));
+        cmd.arg(o_file);
     }
+    add_dependencies(cfg, target, &mut cmd);
 
-    print_forging(&name);
-    if cfg.args.verbose {
-        verbose_command(&cmd);
+    if let Some(implib) = platform.import_lib_name(&lib_name) {
+        let implib = libs_out_dir(cfg).join(implib);
+        cmd.arg(format!("-Wl,--out-implib,{}", implib.display()));
     }
-    else if cfg.args.verbose_hard { 
-        verbose_command_hard(&cmd);
+    add_profile_ldflags(profile, &mut cmd);
+    if let Some(ldflags) = &target.ldflags {
+        cmd.args(check_flags(cfg, state, compiler_id, profile, FlagKind::Link, ldflags, &target.name)?);
     }
+    cmd.args(&cfg.env.ldflags);
     
-    let output = cmd.output().expect("Failed to run ar");
-    
-    if !output.status.success() {
-        bail!("Hammer to rusty, linker failed: {}", String::from_utf8_lossy(&output.stderr))
-    }
-    else { 
-        println!("Forging successful!")
-    }
-    Ok(())
+    let mut inputs = o_files;
+    inputs.extend(external_libraries(cfg, target));
+    run_linker(cfg, state, &mut cmd, &lib_name, &out, &inputs)
 }
 
-pub fn link_executable(config: &Config) -> Result<()> {
-    let target_executable = if cfg!(target_os = "windows") {
-        format!("{}.exe", config.forge.build.output)
-    }
-    else { 
-        config.forge.build.output.clone()
-    };
+pub fn archive_static_library(cfg: &Config, target: &Target, state: &mut BuildState) -> Result<()>{
+    // get a formatted name for the library, based on the output name, and the target platform
+    let name = libs_out_dir(cfg).join(cfg.platform().static_lib_name(target.output_name()));
+    
+    create_forge_sub_dir(&find_o_files_dir(cfg), "libs")?;
     
+    let o_files = load_object_manifest(&target_obj_dir(cfg, target))?;
+    // add the normalized paths
+    let objects: Vec<PathBuf> = o_files.iter().map(|o| PathBuf::from(normalize_path(o))).collect();
+    let mut cmd = cfg.toolchain()?.archive(&name, &objects);
+
+    run_linker(cfg, state, &mut cmd, &name.to_string_lossy(), &name, &o_files)
+}
+
+pub fn link_executable(config: &Config, target: &Target, profile: &Profile, compiler_id: &str, state: &mut BuildState) -> Result<()> {
+    let target_executable = config.platform().executable_name(target.output_name());
     
-    let o_path = find_o_files_dir(&config);
-    let o_files = find_o_files(&o_path);
+    let o_files = load_object_manifest(&target_obj_dir(config, target))?;
     
-    print_forging(&target_executable);
     let cwd = std::env::current_dir().expect("Failed to get current directory");
     
-    let target_path= cwd.join(find_o_files_dir(&config).join(target_executable.clone()));
+    let target_path= cwd.join(find_o_files_dir(config).join(target_executable.clone()));
     
-    let mut cmd= match get_compiler_cmd(&config) {
-        Ok(cmd) => cmd,
-        Err(e) => bail!("Failed to get compiler command: {}", e)
-    };
+    let mut cmd = config.toolchain()?.linker();
     
     // add all object files
-    for o_file in o_files {
+    for o_file in &o_files {
         cmd.arg(o_file);
     }
     
-    #[cfg(any(target_os = "linux", target_os = "macos"))]
-    let r_paths = find_r_paths(&config);
-    // add all library paths
-    if let Some(dependencies) = &config.forge.dependencies {
-        
-        for lib_path in &dependencies.library_paths {
-            cmd.arg(format!("-L{}", lib_path));
-        }
-        
-        // add all rpaths (only linux and macOS)
-        #[cfg(any(target_os = "linux", target_os = "macos"))]
-        for path in &r_paths{
-            cmd.arg(format!("-Wl,-rpath={}", path.to_str().unwrap()).as_str());
-        }
-        
-        // add all libraries
-        for lib in &dependencies.libraries {
-            cmd.arg(format!("-l{}", lib));
-        }
-        
-        for lib in &dependencies.posix_libraries {
-            cmd.arg(format!("-l{}", lib));
-        }
-    }
-    cmd.arg("-o").arg(target_path);
+    add_dependencies(config, target, &mut cmd);
+    cmd.arg("-o").arg(&target_path);
     
-    // add user ldflags
-    if let Some(ldflags) = &config.forge.build.ldflags.clone() {
-        for flag in ldflags {
-            if is_valid_ldflag(flag) { cmd.arg(flag); }
-        }
+    // add profile and user ldflags
+    add_profile_ldflags(profile, &mut cmd);
+    if let Some(ldflags) = &target.ldflags {
+        cmd.args(check_flags(config, state, compiler_id, profile, FlagKind::Link, ldflags, &target.name)?);
     }
+    // flags injected by the environment come last, so that they win
+    cmd.args(&config.env.ldflags);
     
-    if config.args.verbose {
-        verbose_command(&cmd);
-    }
-    else if config.args.verbose_hard { 
-        verbose_command_hard(&cmd);
-    }
-    
-    let output = cmd.output().expect("Failed to run gcc");
-    
-    if !output.status.success() {
-        bail!("Hammer to rusty, linker failed: {}", String::from_utf8_lossy(&output.stderr))
-    }
-    else { 
-        println!("Forging successful!")
-    }
-    Ok(())
+    let mut inputs = o_files;
+    inputs.extend(external_libraries(config, target));
+    run_linker(config, state, &mut cmd, &target_executable, &target_path, &inputs)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Dependencies;
+    use crate::fs_utils::save_object_manifest;
+    use crate::tests::{dummy_config, dummy_target};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_shared_library_link_flags() {
+        let dir = tempdir().unwrap();
+        let mut config = dummy_config(true);
+        config.forge_dir = dir.path().to_path_buf();
+        config.forge.dependencies = Some(Dependencies {
+            libraries: vec!["m".to_string()],
+            library_paths: Vec::new(),
+            include_dirs: Vec::new(),
+            posix_libraries: Vec::new(),
+        });
+        let map = dir.path().join("greet.map");
+        let mut target = dummy_target("greet", TargetKind::Shared);
+        target.ldflags = Some(vec!["-Wl,--no-undefined".to_string(), format!("-Wl,-Map={}", map.display())]);
+
+        let obj_dir = target_obj_dir(&config, &target);
+        std::fs::create_dir_all(&obj_dir).unwrap();
+        let source = dir.path().join("greet.c");
+        std::fs::write(&source, "#include <math.h>\ndouble root(double x) { return sqrt(x); }\n").unwrap();
+        let object = obj_dir.join("greet.o");
+        let status = Command::new("gcc").args(["-fPIC", "-fno-builtin", "-c"]).arg(&source).arg("-o").arg(&object)
+            .status().unwrap();
+        assert!(status.success());
+        save_object_manifest(&obj_dir, &[object]).unwrap();
+
+        let mut state = BuildState::default();
+        let compiler_id = config.toolchain().unwrap().identity().unwrap();
+        link_shared_library(&config, &target, &config.profile().unwrap(), &compiler_id, &mut state).unwrap();
+        assert!(libs_out_dir(&config).join(config.platform().shared_lib_name("greet")).exists());
+        // the target's ldflags were used, and libm resolved `sqrt` under --no-undefined
+        assert!(std::fs::read_to_string(&map).unwrap().contains("libm"));
+    }
+}
@@ -1,24 +1,32 @@
-use std::io::Write;
-use std::path::Path;
-use anyhow::{Context, Result};
-use crate::config::{Config};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use anyhow::{anyhow, bail, Context, Result};
+use colored::Colorize;
+use crate::config::{validate_profile_name, Config, Forge, TargetKind};
 use crate::fs_utils::{
     create_build_dir,
     create_forge_sub_dir,
     ensure_necessary_files,
+    find_o_files_dir,
     init_forge_structure,
-    init_hash_cache_json,
-    std_hash_cache_path,
     std_toml_path};
 
-use crate::arguments::{set_command_defaults, CleanOptions, ForgeArgs, RunOptions};
+use crate::arguments::{set_command_defaults, CleanOptions, ConfigAction, ForgeArgs};
 use clap::Parser;
-use crate::arguments::Command::{Build, Run, Rebuild, Clean, Init, Discover};
+use crate::arguments::Command::{Run, Debug, Rebuild, Clean, Init, Discover, CheckConfig, Config as ConfigCmd, Cache};
 use crate::compile::compile;
 use crate::discovery::discover;
 use crate::linker::link;
-use crate::ui::{print_cleaning, verbose_command, verbose_command_hard};
+use crate::error::ForgeError;
+use crate::ui::{print_cleaning, print_error};
+use crate::runner::{debug_target, execute_target};
 use crate::utils::derive_clean_options;
+use crate::build_state::StateStore;
+use crate::cache::cache_command;
+use crate::config_edit::{parse_value, ConfigDocument};
+use crate::ui::print_config_error;
+use crate::validation::{check_config, validate_config};
+use crate::workspace::{load_members, read_workspace, Workspace};
 
 mod config;
 mod fs_utils;
@@ -30,8 +38,29 @@ mod tests;
 mod ui;
 mod arguments;
 mod discovery;
+mod workspace;
+mod validation;
+mod config_edit;
+mod build_state;
+mod cache;
+mod error;
+mod runner;
+mod toolchain;
+mod build_env;
+mod flags;
+mod platform;
 
-fn main() -> Result<()>{
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            print_error(&e.to_string());
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, ForgeError> {
     // parse command line arguments
     let mut args = ForgeArgs::parse();
     
@@ -39,156 +68,277 @@ fn main() -> Result<()>{
     set_command_defaults(&mut args.command);
 
     // get the current working directory
-    let cwd = match std::env::current_dir() {
-        Ok(path) => path,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
-        }
-    };
+    let cwd = std::env::current_dir()?;
     if let Init(opt) = &args.command{
-        if let Err(e) = init_forge_structure(opt) {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
-        }
-        std::process::exit(0);
+        init_forge_structure(opt).map_err(ForgeError::Fs)?;
+        return Ok(ExitCode::SUCCESS);
     }
-    if let Err(e) = ensure_necessary_files() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    
+    // validate the configuration before doing anything else
+    let toml_path = std_toml_path().map_err(ForgeError::Fs)?;
+    // editing happens before validation, so a broken configuration can be fixed
+    if let ConfigCmd(action) = &args.command {
+        edit_config(&toml_path, action).map_err(ForgeError::Config)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if toml_path.exists() {
+        check_config(&toml_path).map_err(ForgeError::Config)?;
     }
     
-    let config = Config::read(&args);
+    if let Some(workspace) = read_workspace(&toml_path).map_err(ForgeError::Config)? {
+        return run_workspace(&args, &cwd, &workspace);
+    }
     
-    let targets = &config.forge.project.targets;
-    if targets.iter().any(|t| t == "static" || t == "shared") {
-        for dir in ["libs/out", "libs/obj"] {
-            if let Err(e) = create_forge_sub_dir(dir) {
-                eprintln!("Error creating {}: {}", dir, e);
-                std::process::exit(1);
-            }
-        }
+    if let CheckConfig = args.command {
+        println!("{}", "Configuration is valid.".green());
+        return Ok(ExitCode::SUCCESS);
     }
     
-    if let Err(e) = create_build_dir(&args.command) {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);   
+    ensure_necessary_files().map_err(ForgeError::Config)?;
+    
+    let config = Config::read(&args).map_err(ForgeError::Config)?;
+    
+    if let Clean(mut opt) = args.command.clone() {
+        clean(&config.forge_dir, &config.output_dir(), &profiles(&config.forge), &mut opt).map_err(ForgeError::Fs)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Cache(action) = &args.command {
+        cache_command(&config.forge_dir, action).map_err(ForgeError::Fs)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Discover(options) = &args.command {
+        discover(options, toml_path).context("Error discovering").map_err(ForgeError::Fs)?;
+        return Ok(ExitCode::SUCCESS);
     }
     
-    match args.command.clone() {
-        Build(_) => {
-            compile(&config).context("Error compiling")?;
-            link(&config).expect("Error linking");
-        }
-        Rebuild(opt) => {
-            let mut clean_opt = derive_clean_options(&opt);
-            clean(&cwd, &mut clean_opt);
-            compile(&config).expect("Error compiling");
-            link(&config).expect("Error linking");
-        }
-        Run(mut opt) => {
-            compile(&config).expect("Error compiling");
-            link(&config).expect("Error linking");
-            execute_target(&config, &cwd, &mut opt);
-        }
-        Clean(mut opt) => {
-            clean(&cwd, &mut opt);
-        }
-        Discover(options) => {
-            discover(&options, std_toml_path()
-                .expect("Error generating standard .toml path")
-            ).expect("Error discovering.");
-        }
-        _ => {
-            print!("Hi! This will never be printed.")
-        } // not necessary, just for compiler error suppression
+    if let Rebuild(opt) = &args.command {
+        let mut clean_opt = derive_clean_options(opt);
+        clean(&config.forge_dir, &config.output_dir(), &profiles(&config.forge), &mut clean_opt).map_err(ForgeError::Fs)?;
     }
-    Ok(())
+    build_project(&config)?;
+    
+    if let Run(mut opt) = args.command.clone() {
+        return execute_target(&config, &cwd, &mut opt).map_err(ForgeError::Other);
+    }
+    if let Debug(opt) = &args.command {
+        return debug_target(&config, &cwd, opt).map_err(ForgeError::Other);
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn execute_target(config: &Config, cwd: &Path, opt: &mut RunOptions) {
-    // needs to mutable on windows!
-    #[allow(unused_mut)]
-    let mut exe_name = config.forge.build.output.clone();
-
-    // add .exe extension on windows
-    #[cfg(target_os = "windows")]
-    exe_name.push_str(".exe");
+/// compiles and links all targets of a single project
+fn build_project(config: &Config) -> Result<(), ForgeError> {
+    let targets = config.forge.resolve_targets().map_err(ForgeError::Config)?;
+    if targets.iter().any(|t| t.kind != TargetKind::Bin) {
+        create_forge_sub_dir(&find_o_files_dir(config), "libs")
+            .context("Error creating the libs directory")
+            .map_err(ForgeError::Fs)?;
+    }
+    
+    // make sure the selected profile exists, before creating its output directory
+    config.profile().map_err(ForgeError::Config)?;
+    // and that the compiler knows the selected C standard
+    if config.forge.build.c_standard.is_some() {
+        let compiler_id = config.toolchain().and_then(|t| t.identity()).map_err(ForgeError::Config)?;
+        config.language_flags(&compiler_id).map_err(ForgeError::Config)?;
+    }
+    
+    create_build_dir(&config.output_dir(), &config.args.command).map_err(ForgeError::Fs)?;
+    
+    compile(config).map_err(|e| ForgeError::in_stage(ForgeError::Compile, e))?;
+    link(config).map_err(|e| ForgeError::in_stage(ForgeError::Link, e))?;
+    Ok(())
+}
 
-    let mut exe_path = cwd.join("forge");
-    if opt.debug {
-        exe_path.push("debug");
+/// builds every member of a workspace in dependency order,
+/// sharing one output directory and hash cache at the workspace root
+fn run_workspace(args: &ForgeArgs, root: &Path, workspace: &Workspace) -> Result<ExitCode, ForgeError> {
+    for member in &workspace.members {
+        check_config(&root.join(member).join("RustyForge.toml")).map_err(ForgeError::Config)?;
     }
-    else if opt.release {
-        exe_path.push("release");
+    if let CheckConfig = args.command {
+        println!("{}", "Configuration is valid.".green());
+        return Ok(ExitCode::SUCCESS);
     }
-    exe_path = exe_path.join(exe_name);
-    let mut cmd = std::process::Command::new(exe_path);
     
-    cmd.args(opt.args.clone());
+    let forge_dir = root.join("forge");
+    let out_dir = match &args.target {
+        Some(triple) => forge_dir.join(triple),
+        None => forge_dir.clone(),
+    };
+    
+    let members = load_members(root, workspace).map_err(ForgeError::Config)?;
+    let mut profile_names: Vec<String> = members.iter().flat_map(|member| profiles(&member.forge)).collect();
+    profile_names.sort();
+    profile_names.dedup();
+    match &args.command {
+        Clean(opt) => {
+            clean(&forge_dir, &out_dir, &profile_names, &mut opt.clone()).map_err(ForgeError::Fs)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Rebuild(opt) => {
+            let mut clean_opt = derive_clean_options(opt);
+            clean(&forge_dir, &out_dir, &profile_names, &mut clean_opt).map_err(ForgeError::Fs)?;
+        }
+        Cache(action) => {
+            cache_command(&forge_dir, action).map_err(ForgeError::Fs)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Discover(_) => return Err(ForgeError::Config(anyhow!(
+            "Discover is not supported for workspaces, run it inside a member."
+        ))),
+        _ => {}
+    }
     
-    if config.args.verbose {
-        verbose_command(&cmd);
+    let mut configs = Vec::new();
+    for member in &members {
+        std::env::set_current_dir(&member.path)
+            .with_context(|| format!("Could not enter workspace member: {}", member.path.display()))
+            .map_err(ForgeError::Fs)?;
+        println!("[{}]", member.name.bold());
+        let mut config = Config::read(args).map_err(ForgeError::Config)?;
+        config.forge_dir = forge_dir.clone();
+        config.member = Some(member.name.clone());
+        build_project(&config)?;
+        configs.push((member.path.clone(), config));
     }
-    else if config.args.verbose_hard {
-        verbose_command_hard(&cmd);
+    std::env::set_current_dir(root)?;
+    
+    let bin = match &args.command {
+        Run(opt) => opt.bin.as_ref(),
+        Debug(opt) => opt.bin.as_ref(),
+        _ => return Ok(ExitCode::SUCCESS),
+    };
+    // run the requested executable, or the first one of the workspace
+    let (path, config) = configs.iter()
+        .find(|(_, config)| config.forge.resolve_targets().is_ok_and(|targets| {
+            targets.iter().any(|t| {
+                t.kind == TargetKind::Bin && bin.is_none_or(|name| &t.name == name)
+            })
+        }))
+        .context("No executable target to run in the workspace")?;
+    std::env::set_current_dir(path)?;
+    match &args.command {
+        Debug(opt) => debug_target(config, path, opt).map_err(ForgeError::Other),
+        Run(opt) => execute_target(config, path, &mut opt.clone()).map_err(ForgeError::Other),
+        _ => Ok(ExitCode::SUCCESS),
     }
+}
 
-    let output = cmd.output().expect("Error running executable.");
+/// reads or edits a single key of the RustyForge.toml
+fn edit_config(toml_path: &Path, action: &ConfigAction) -> Result<()> {
+    let mut doc = ConfigDocument::open(toml_path)?;
+    let changed = match action {
+        ConfigAction::Get { key } => {
+            match doc.get(key)? {
+                Some(value) => println!("{}", value),
+                None => bail!("Key not found: {}", key),
+            }
+            return Ok(());
+        }
+        ConfigAction::Set { key, value } => {
+            doc.set(key, parse_value(value))?;
+            true
+        }
+        ConfigAction::Add { key, value } => doc.add(key, parse_value(value))?,
+        ConfigAction::Remove { key, value } => doc.remove(key, value.as_deref().map(parse_value))?,
+    };
+    if !changed {
+        println!("Nothing to change.");
+        return Ok(());
+    }
+    doc.save()?;
+    
+    // point out problems the edit introduced, without rejecting it
+    for error in validate_config(toml_path)? {
+        print_config_error(&error);
+    }
+    Ok(())
+}
 
-    std::io::stdout().write_all(&output.stdout).expect("Error writing stdout.");
-    std::io::stderr().write_all(&output.stderr).expect("Error writing stderr.");
+/// the names of the `[profile.<name>]` tables that are usable as output directories
+fn profiles(forge: &Forge) -> Vec<String> {
+    forge.profiles.keys().filter(|name| validate_profile_name(name).is_ok()).cloned().collect()
 }
 
-fn clean(cwd: &Path, opt: &mut CleanOptions) {
+/// removes the output in `out_dir`, which is `forge_dir` or the directory of a target triple inside it.
+/// `profiles` are the names of the `[profile.<name>]` tables, whose output is removed with everything else
+fn clean(forge_dir: &Path, out_dir: &Path, profiles: &[String], opt: &mut CleanOptions) -> Result<()> {
+    if let Some(profile) = &opt.profile {
+        validate_profile_name(profile)?;
+    }
     print_cleaning();
+    // wait for running builds, before removing their output
+    let mut store = StateStore::open(&forge_dir.join(".forge").join("build_state.json"))?;
     // if none are specified, clean everything
-    if !opt.debug && !opt.release && !opt.libs {
+    if !opt.debug && !opt.release && !opt.libs && opt.profile.is_none() {
         opt.debug = true;
         opt.release = true;
         opt.libs = true;   
     }
+    // cleaning everything includes the output of user defined profiles. Next to the profiles,
+    // `forge/` holds the output of the target triples, which only `clean --target` removes
+    if opt.debug && opt.release && opt.libs {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        if out_dir == forge_dir {
+            dirs.extend(profiles.iter().map(|profile| out_dir.join(profile)));
+        }
+        else if let Ok(entries) = std::fs::read_dir(out_dir) {
+            dirs.extend(entries.flatten().map(|entry| entry.path()));
+        }
+        for path in dirs.iter().filter(|path| path.is_dir()) {
+            std::fs::remove_dir_all(path)
+                .with_context(|| format!("Error removing profile directory: {}", path.display()))?;
+        }
+    }
+    if let Some(profile) = &opt.profile {
+        let path = out_dir.join(profile);
+        if path.exists() {
+            std::fs::remove_dir_all(&path)
+                .with_context(|| format!("Error removing profile directory: {}", path.display()))?;
+        }
+    }
     if opt.debug {
-        let path = cwd.join("forge").join("debug");
+        let path = out_dir.join("debug");
         if path.exists() {
-            std::fs::remove_dir_all(path).expect("Error removing debug directory.");
+            std::fs::remove_dir_all(&path).context("Error removing debug directory")?;
         }
     }
     if opt.release {
-        let path = cwd.join("forge").join("release");
+        let path = out_dir.join("release");
         if path.exists() {
-            std::fs::remove_dir_all(path).expect("Error removing release directory.");
+            std::fs::remove_dir_all(&path).context("Error removing release directory")?;
         }
     }
     if opt.libs {
-        let libs_path = cwd.join("forge").join("libs");
-        if libs_path.exists() {
-            std::fs::remove_dir_all(libs_path).expect("Error removing libs directory.");
+        // the libraries of every profile, and the shared `libs` directory of older versions
+        let mut libs_paths = vec![out_dir.join("libs")];
+        if let Ok(entries) = std::fs::read_dir(out_dir) {
+            libs_paths.extend(entries.flatten().map(|entry| entry.path().join("libs")));
+        }
+        for libs_path in libs_paths.iter().filter(|path| path.is_dir()) {
+            std::fs::remove_dir_all(libs_path)
+                .with_context(|| format!("Error removing libs directory: {}", libs_path.display()))?;
         }
     }
-    let json_path = cwd.join("forge").join(".forge").join("hash_cache.json");
-    if json_path.exists()  {
-        std::fs::remove_file(json_path).expect("Error removing hash cache file.");
-    }
-    // reinitialize empty hash_cache.json file
-    if let Err(e) = init_hash_cache_json(std_hash_cache_path().expect("Error getting hash cache path.")){
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    // forget the removed objects and outputs
+    store.state.objects.retain(|object, _| Path::new(object).exists());
+    store.state.outputs.retain(|output, _| Path::new(output).exists());
+    store.save()?;
+    // left behind by versions before the build state
+    let legacy_path = forge_dir.join(".forge").join("hash_cache.json");
+    if legacy_path.exists() {
+        std::fs::remove_file(&legacy_path).context("Error removing hash cache file")?;
     }
     // reinitialize forge directory
-    if opt.debug {
-        if let Err(e) = create_forge_sub_dir("debug") {
-            eprintln!("Error: {}", e);
-        }
+    if let Some(profile) = &opt.profile {
+        create_forge_sub_dir(out_dir, profile)?;
     }
-    else if opt.release {
-        if let Err(e) = create_forge_sub_dir("release") {
-            eprintln!("Error: {}", e);
-        }
+    else if opt.debug {
+        create_forge_sub_dir(out_dir, "debug")?;
     }
-    else if opt.libs {
-        if let Err(e) = create_forge_sub_dir("libs") {
-            eprintln!("Error: {}", e);
-        }
+    else if opt.release {
+        create_forge_sub_dir(out_dir, "release")?;
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -0,0 +1,95 @@
+/// The operating system the build output is for. Artifact names and linker
+/// options follow this platform, which is the host unless cross compiling.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Platform {
+    Linux,
+    MacOs,
+    Windows,
+}
+
+impl Platform {
+    pub fn host() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        }
+        else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        }
+        else {
+            Platform::Linux
+        }
+    }
+
+    /// the platform of a target triple like `aarch64-linux-gnu` or `x86_64-w64-mingw32`,
+    /// every triple that is neither windows nor apple is treated like linux
+    pub fn of_triple(triple: &str) -> Self {
+        let triple = triple.to_lowercase();
+        if ["windows", "mingw", "cygwin"].iter().any(|os| triple.contains(os)) {
+            Platform::Windows
+        }
+        else if ["apple", "darwin", "macos"].iter().any(|os| triple.contains(os)) {
+            Platform::MacOs
+        }
+        else {
+            Platform::Linux
+        }
+    }
+
+    pub fn executable_name(self, name: &str) -> String {
+        match self {
+            Platform::Windows => format!("{}.exe", name),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn static_lib_name(self, name: &str) -> String {
+        format!("lib{}.a", name)
+    }
+
+    pub fn shared_lib_name(self, name: &str) -> String {
+        match self {
+            Platform::Linux => format!("lib{}.so", name),
+            Platform::MacOs => format!("lib{}.dylib", name),
+            Platform::Windows => format!("lib{}.dll", name),
+        }
+    }
+
+    /// the import library written next to a DLL, that executables link against
+    pub fn import_lib_name(self, name: &str) -> Option<String> {
+        match self {
+            Platform::Windows => Some(format!("lib{}.dll.a", name)),
+            _ => None,
+        }
+    }
+
+    /// whether executables can find shared libraries through an rpath
+    pub fn has_rpath(self) -> bool {
+        self != Platform::Windows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_of_triple() {
+        assert_eq!(Platform::of_triple("aarch64-linux-gnu"), Platform::Linux);
+        assert_eq!(Platform::of_triple("arm-none-eabi"), Platform::Linux);
+        assert_eq!(Platform::of_triple("x86_64-w64-mingw32"), Platform::Windows);
+        assert_eq!(Platform::of_triple("x86_64-pc-windows-gnu"), Platform::Windows);
+        assert_eq!(Platform::of_triple("aarch64-apple-darwin"), Platform::MacOs);
+    }
+
+    #[test]
+    fn test_artifact_names() {
+        assert_eq!(Platform::Windows.executable_name("app"), "app.exe");
+        assert_eq!(Platform::Linux.executable_name("app"), "app");
+        assert_eq!(Platform::Linux.shared_lib_name("core"), "libcore.so");
+        assert_eq!(Platform::MacOs.shared_lib_name("core"), "libcore.dylib");
+        assert_eq!(Platform::Windows.shared_lib_name("core"), "libcore.dll");
+        assert_eq!(Platform::Windows.import_lib_name("core").as_deref(), Some("libcore.dll.a"));
+        assert_eq!(Platform::Linux.import_lib_name("core"), None);
+        assert_eq!(Platform::Windows.static_lib_name("core"), "libcore.a");
+    }
+}
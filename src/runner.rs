@@ -0,0 +1,267 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitCode, ExitStatus};
+use anyhow::{Context, Result};
+use crate::arguments::{DebugOptions, RunOptions};
+use crate::config::{Config, TargetKind};
+use crate::toolchain::Toolchain;
+use crate::fs_utils::{find_o_files_dir, libs_out_dir};
+use crate::ui::{verbose_command, verbose_command_hard};
+
+/// Runs the requested executable target, or the first one, with the terminal of rustyforge.
+/// Returns the exit code of the program, to exit with.
+pub fn execute_target(config: &Config, cwd: &Path, opt: &mut RunOptions) -> Result<ExitCode> {
+    let mut cmd = program_command(config, cwd, opt.bin.as_deref(), &opt.args)?;
+    
+    if config.args.verbose {
+        verbose_command(&cmd);
+    }
+    else if config.args.verbose_hard {
+        verbose_command_hard(&cmd);
+    }
+
+    // stdin, stdout and stderr are inherited, so the program can be used interactively
+    let child = cmd.spawn()
+        .with_context(|| format!("Could not run executable: {}", cmd.get_program().to_string_lossy()))?;
+    let status = wait_forwarding_signals(child)?;
+    Ok(exit_code(status))
+}
+
+/// Runs an executable target of the debug profile under the debugger matching the compiler.
+/// Returns the exit code of the debugger.
+pub fn debug_target(config: &Config, cwd: &Path, opt: &DebugOptions) -> Result<ExitCode> {
+    let program = program_command(config, cwd, opt.bin.as_deref(), &opt.args)?;
+    let mut cmd = debugger_command(config.toolchain()?.as_ref(), &program);
+    
+    if config.args.verbose {
+        verbose_command(&cmd);
+    }
+    else if config.args.verbose_hard {
+        verbose_command_hard(&cmd);
+    }
+
+    let child = cmd.spawn()
+        .with_context(|| format!("Could not start {}, is it installed?", cmd.get_program().to_string_lossy()))?;
+    let status = wait_forwarding_signals(child)?;
+    Ok(exit_code(status))
+}
+
+/// Wraps the command of a program into a debugger session: gdb for gcc, lldb for clang.
+/// The debugger starts in the working directory and with the environment of the program,
+/// which the debugger passes on to it.
+fn debugger_command(toolchain: &dyn Toolchain, program: &Command) -> Command {
+    let mut cmd = toolchain.debugger();
+    cmd.arg(program.get_program()).args(program.get_args());
+    if let Some(dir) = program.get_current_dir() {
+        cmd.current_dir(dir);
+    }
+    for (key, value) in program.get_envs() {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+    cmd
+}
+
+/// The command starting an executable target of the project in `project_dir`, set up by `[run]`.
+/// The arguments given on the command line replace the default arguments of `[run]`.
+pub fn program_command(config: &Config, project_dir: &Path, bin: Option<&str>, args: &[String]) -> Result<Command> {
+    let targets = config.forge.resolve_targets()?;
+    let target = match bin {
+        Some(name) => targets.iter()
+            .find(|t| t.kind == TargetKind::Bin && t.name == name)
+            .with_context(|| format!("No executable target named: {}", name))?,
+        None => targets.iter()
+            .find(|t| t.kind == TargetKind::Bin)
+            .context("No executable target to run")?,
+    };
+    let exe_name = config.platform().executable_name(target.output_name());
+    let exe_path = project_dir.join(find_o_files_dir(config)).join(exe_name);
+    let mut cmd = Command::new(exe_path);
+    
+    let settings = config.forge.run.clone().unwrap_or_default();
+    cmd.args(if args.is_empty() { &settings.args } else { args });
+    cmd.current_dir(project_dir.join(settings.cwd.as_deref().unwrap_or(".")));
+    cmd.envs(&settings.env);
+    
+    // shared libraries of the project and its dependencies are found without installing them
+    let existing = settings.env.get(LOADER_PATH_VAR).map(OsString::from)
+        .or_else(|| std::env::var_os(LOADER_PATH_VAR));
+    cmd.env(LOADER_PATH_VAR, loader_path(&library_dirs(config, project_dir), existing.as_deref())?);
+    Ok(cmd)
+}
+
+/// environment variable the dynamic loader searches for shared libraries
+#[cfg(target_os = "windows")]
+const LOADER_PATH_VAR: &str = "PATH";
+#[cfg(target_os = "macos")]
+const LOADER_PATH_VAR: &str = "DYLD_LIBRARY_PATH";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LOADER_PATH_VAR: &str = "LD_LIBRARY_PATH";
+
+/// the library output of the project and the library paths of its dependencies
+fn library_dirs(config: &Config, project_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![project_dir.join(libs_out_dir(config))];
+    if let Some(dependencies) = &config.forge.dependencies {
+        for dir in dependencies.library_paths.iter().map(|p| project_dir.join(p)) {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+/// `dirs` in front of the existing search path of the loader
+fn loader_path(dirs: &[PathBuf], existing: Option<&OsStr>) -> Result<OsString> {
+    let existing = existing.map(|e| std::env::split_paths(e).collect::<Vec<_>>()).unwrap_or_default();
+    std::env::join_paths(dirs.iter().cloned().chain(existing))
+        .context("Library path contains the path separator")
+}
+
+/// Waits for the program, without rustyforge being stopped before it.
+/// Ctrl-C reaches the program from the terminal, as it is in the same process group.
+/// Termination requests sent to rustyforge alone are forwarded to the program.
+#[cfg(unix)]
+fn wait_forwarding_signals(mut child: Child) -> Result<ExitStatus> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGINT, SIGQUIT, SIGTERM, SIGHUP])?;
+    let handle = signals.handle();
+    let pid = child.id() as libc::pid_t;
+    let forwarder = std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if signal == SIGTERM || signal == SIGHUP {
+                // SAFETY: kill has no memory safety requirements. The program is reaped only
+                // after the forwarder stopped, so until then the pid is the program's, if only as a zombie
+                unsafe { libc::kill(pid, signal) };
+            }
+        }
+    });
+    let exited = wait_without_reaping(pid);
+    handle.close();
+    let _ = forwarder.join();
+    let status = child.wait();
+    exited?;
+    Ok(status?)
+}
+
+/// waits until the process exited, leaving it to be reaped by `Child::wait`
+#[cfg(unix)]
+fn wait_without_reaping(pid: libc::pid_t) -> std::io::Result<()> {
+    loop {
+        // SAFETY: siginfo_t is plain data, all zeroes is a valid value
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `info` is a valid siginfo_t for waitid to fill in
+        let result = unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        if result == 0 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_forwarding_signals(mut child: Child) -> Result<ExitStatus> {
+    Ok(child.wait()?)
+}
+
+/// The exit code of the program. A program killed by a signal exits with 128 + the signal,
+/// like it would in a shell.
+fn exit_code(status: ExitStatus) -> ExitCode {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return ExitCode::from(128u8.wrapping_add(signal as u8));
+    }
+    match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        // exit codes above 255 (windows) must not turn into success
+        Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+        None => ExitCode::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::config::RunSettings;
+    use crate::tests::dummy_config;
+    use crate::toolchain::{ClangLike, GccLike, ToolchainConfig, Tools};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code() {
+        use std::os::unix::process::ExitStatusExt;
+        // raw wait statuses: the exit code is in the second byte, a signal in the first
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), ExitCode::SUCCESS);
+        assert_eq!(exit_code(ExitStatus::from_raw(7 << 8)), ExitCode::from(7));
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGINT)), ExitCode::from(130));
+    }
+
+    #[test]
+    fn test_program_command() {
+        let project_dir = Path::new("/project");
+        let mut config = dummy_config(true);
+        config.forge.run = Some(RunSettings {
+            env: BTreeMap::from([("LOG".to_string(), "debug".to_string())]),
+            cwd: Some("data".to_string()),
+            args: vec!["--fast".to_string()],
+        });
+
+        let cmd = program_command(&config, project_dir, None, &[]).unwrap();
+        assert_eq!(cmd.get_program(), project_dir.join("forge/debug/dummy"));
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["--fast"]);
+        assert_eq!(cmd.get_current_dir(), Some(project_dir.join("data").as_path()));
+        let envs: BTreeMap<_, _> = cmd.get_envs().collect();
+        assert_eq!(envs[OsStr::new("LOG")], Some(OsStr::new("debug")));
+        let loader_path = envs[OsStr::new(LOADER_PATH_VAR)].unwrap();
+        assert_eq!(std::env::split_paths(loader_path).next(), Some(project_dir.join("forge/debug/libs")));
+
+        // arguments on the command line replace the default ones
+        let cmd = program_command(&config, project_dir, None, &["-v".to_string()]).unwrap();
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["-v"]);
+    }
+
+    #[test]
+    fn test_debugger_command() {
+        let mut program = Command::new("/project/forge/debug/app");
+        program.arg("--fast").current_dir("/project").env("LOG", "debug");
+
+        let tools = Tools::new("gcc".to_string(), &ToolchainConfig::default());
+        let gdb = debugger_command(&GccLike { tools: tools.clone() }, &program);
+        assert_eq!(gdb.get_program(), "gdb");
+        assert_eq!(gdb.get_args().collect::<Vec<_>>(), ["--args", "/project/forge/debug/app", "--fast"]);
+        assert_eq!(gdb.get_current_dir(), Some(Path::new("/project")));
+        assert_eq!(gdb.get_envs().collect::<Vec<_>>(), [(OsStr::new("LOG"), Some(OsStr::new("debug")))]);
+
+        let lldb = debugger_command(&ClangLike { tools }, &program);
+        assert_eq!(lldb.get_program(), "lldb");
+        assert_eq!(lldb.get_args().collect::<Vec<_>>(), ["--", "/project/forge/debug/app", "--fast"]);
+    }
+
+    #[test]
+    fn test_loader_path() {
+        let dirs = [PathBuf::from("/project/forge/debug/libs"), PathBuf::from("/project/libs")];
+        let existing = std::env::join_paths(["/usr/local/lib"]).unwrap();
+        let path = loader_path(&dirs, Some(&existing)).unwrap();
+        assert_eq!(
+            std::env::split_paths(&path).collect::<Vec<_>>(),
+            [dirs[0].clone(), dirs[1].clone(), PathBuf::from("/usr/local/lib")]
+        );
+        assert_eq!(loader_path(&dirs[..1], None).unwrap(), dirs[0].as_os_str());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_status_of_program() {
+        let child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let status = wait_forwarding_signals(child).unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+}
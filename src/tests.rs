@@ -1,36 +1,105 @@
-use std::path::{Path};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
 use crate::arguments::Command::{Rebuild};
 use crate::arguments::{BuildOptions, ForgeArgs};
-use crate::config::{Build, CompilerKind, Config, Forge, Project};
+use crate::config::{Build, CompilerKind, Config, Forge, Project, Target, TargetKind};
 
 // usage is not recognized by rustfmt
 #[allow(dead_code)]
 pub fn dummy_config(debug: bool) -> Config {
     Config {
         compiler: CompilerKind::GCC,
+        cc: None,
+        forge_dir: PathBuf::from("forge"),
+        member: None,
+        env: Default::default(),
         args: ForgeArgs {
             verbose: false,
             verbose_hard: false,
-            command: Rebuild(BuildOptions {debug, compiler: None, release: !debug}),
+            jobs: None,
+            keep_going: false,
+            target: None,
+            command: Rebuild(BuildOptions {debug, compiler: None, release: !debug, profile: None}),
         },
         forge: Forge {
             build: Build {
                 compiler: Some("gcc".to_string()),
-                output: "dummy".to_string(),
+                output: Some("dummy".to_string()),
                 cflags: None,
                 ldflags: None,
                 src: Vec::new(),
+                exclude: Vec::new(),
                 include_dirs: Vec::new(),
+                flag_policy: Default::default(),
+                c_standard: None,
+                warnings: None,
+                warnings_as_errors: false,
             },
             project: Project {
                 name: "dummy".to_string(),
                 targets: vec!["bin".to_string()],
             },
             dependencies: None,
+            targets: Vec::new(),
+            profiles: Default::default(),
+            run: None,
+            toolchain: None,
+            cross: Default::default(),
         }
     }
 }
 
+// usage is not recognized by rustfmt
+#[allow(dead_code)]
+pub fn dummy_target(name: &str, kind: TargetKind) -> Target {
+    Target {
+        name: name.to_string(),
+        kind,
+        src: Vec::new(),
+        exclude: Vec::new(),
+        include_dirs: Vec::new(),
+        cflags: None,
+        ldflags: None,
+        output: None,
+    }
+}
+
+/// the working directory is shared by the whole process, but tests run on parallel threads
+static CWD: Mutex<()> = Mutex::new(());
+
+/// Holds the working directory for a test that changes or depends on it,
+/// and changes back to the original directory when it is dropped.
+// usage is not recognized by rustfmt
+#[allow(dead_code)]
+pub struct CwdGuard {
+    original: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+// usage is not recognized by rustfmt
+#[allow(dead_code)]
+impl CwdGuard {
+    /// keeps the working directory as it is
+    pub fn hold() -> Self {
+        // the lock of a failed test is poisoned, but the directory was restored all the same
+        let lock = CWD.lock().unwrap_or_else(|e| e.into_inner());
+        CwdGuard { original: std::env::current_dir().unwrap(), _lock: lock }
+    }
+
+    /// changes into `dir`
+    pub fn enter(dir: &Path) -> Self {
+        let guard = Self::hold();
+        std::env::set_current_dir(dir).unwrap();
+        guard
+    }
+}
+
+impl Drop for CwdGuard {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.original);
+    }
+}
+
 // usage is not recognized by rustfmt
 #[allow(dead_code)]
 fn clear_dir(dir: &Path) -> std::io::Result<()> {
@@ -53,17 +122,19 @@ fn clear_dir(dir: &Path) -> std::io::Result<()> {
 #[cfg(test)]
 mod integration_tests {
     use std::env;
-    use std::path::PathBuf;
     use crate::compile::compile;
-    use crate::fs_utils::init_hash_cache_json;
+    use crate::config::{Dependencies, Profile};
+    use crate::arguments::Command::Build;
+    use crate::run_workspace;
+    use crate::workspace::read_workspace;
     use crate::linker::link;
     use super::*;
     
     #[test]
     fn test_valid_project_gcc(){
         let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let org_cwd = cwd.clone();
         let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
         // make sure the build directory is empty
         let debug_path  = tests_path.join("forge").join("debug");
         // create the debug dir if it does not exist!
@@ -71,24 +142,20 @@ mod integration_tests {
             std::fs::create_dir_all(&debug_path).unwrap();
         }
         clear_dir(&debug_path).unwrap();
-        
-        env::set_current_dir(&tests_path).unwrap();
-        
+
         let mut config = dummy_config(true);
         config.forge.build.src.push("lib.c".to_string());
         config.forge.build.src.push("main.c".to_string());
         config.forge.build.include_dirs.push("include".to_string());
 
-        init_hash_cache_json(tests_path.join("forge").join(".forge")).unwrap();
-        
         let compile_res = compile(&config);
         assert!(compile_res.is_ok());
         
         let link_res = link(&config);
         assert!(link_res.is_ok());
 
-        let main_o_path = debug_path.join("main.o");
-        let lib_o_path = debug_path.join("lib.o");
+        let main_o_path = debug_path.join("obj").join("bin").join("main.o");
+        let lib_o_path = debug_path.join("obj").join("bin").join("lib.o");
         #[cfg(target_os = "windows")]
         let exe_path = debug_path.join("dummy.exe");
         #[cfg(not(target_os = "windows"))]
@@ -99,35 +166,290 @@ mod integration_tests {
         
         // delete the build contents
         clear_dir(&debug_path).unwrap();
-        
-        env::set_current_dir(org_cwd).unwrap();
     }
     
+    #[test]
+    fn test_multiple_targets_gcc(){
+        let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
+        let debug_path  = tests_path.join("forge").join("debug");
+        if !debug_path.exists() {
+            std::fs::create_dir_all(&debug_path).unwrap();
+        }
+        clear_dir(&debug_path).unwrap();
+
+        let mut config = dummy_config(true);
+        config.forge.build.include_dirs.push("include".to_string());
+        let mut first = dummy_target("first", TargetKind::Bin);
+        first.src = vec!["lib.c".to_string(), "main.c".to_string()];
+        let mut second = dummy_target("second", TargetKind::Bin);
+        second.src = vec!["*.c".to_string()];
+        second.output = Some("second_exe".to_string());
+        config.forge.targets = vec![first, second];
+
+        assert!(compile(&config).is_ok());
+        assert!(link(&config).is_ok());
+
+        assert!(debug_path.join("obj").join("first").join("main.o").exists());
+        assert!(debug_path.join("obj").join("second").join("main.o").exists());
+        #[cfg(target_os = "windows")]
+        let (first_exe, second_exe) = (debug_path.join("first.exe"), debug_path.join("second_exe.exe"));
+        #[cfg(not(target_os = "windows"))]
+        let (first_exe, second_exe) = (debug_path.join("first"), debug_path.join("second_exe"));
+        assert!(first_exe.exists());
+        assert!(second_exe.exists());
+
+        clear_dir(&debug_path).unwrap();
+    }
+
+    #[test]
+    fn test_custom_profile_gcc(){
+        let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
+        let bench_path  = tests_path.join("forge").join("bench");
+
+        let mut config = dummy_config(false);
+        config.args.command = Rebuild(BuildOptions {
+            debug: false, release: false, compiler: None, profile: Some("bench".to_string())
+        });
+        config.forge.build.src.push("lib.c".to_string());
+        config.forge.build.src.push("main.c".to_string());
+        config.forge.build.include_dirs.push("include".to_string());
+        config.forge.profiles.insert("bench".to_string(), Profile {
+            inherits: Some("release".to_string()),
+            cflags: None,
+            ldflags: None,
+            defines: Some(vec!["BENCH=1".to_string()]),
+        });
+
+        assert!(compile(&config).is_ok());
+        assert!(link(&config).is_ok());
+
+        assert!(bench_path.join("obj").join("bin").join("main.o").exists());
+        #[cfg(target_os = "windows")]
+        let exe_path = bench_path.join("dummy.exe");
+        #[cfg(not(target_os = "windows"))]
+        let exe_path = bench_path.join("dummy");
+        assert!(exe_path.exists());
+
+        std::fs::remove_dir_all(&bench_path).unwrap();
+    }
+
+    #[test]
+    fn test_link_skipped_when_up_to_date_gcc(){
+        let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
+        let relink_path  = tests_path.join("forge").join("relink");
+
+        let mut config = dummy_config(true);
+        config.args.command = Build(BuildOptions {
+            debug: false, release: false, compiler: None, profile: Some("relink".to_string())
+        });
+        config.forge.build.src.push("lib.c".to_string());
+        config.forge.build.src.push("main.c".to_string());
+        config.forge.build.include_dirs.push("include".to_string());
+        config.forge.profiles.insert("relink".to_string(), Profile {
+            inherits: Some("debug".to_string()),
+            cflags: None,
+            ldflags: None,
+            defines: None,
+        });
+        #[cfg(target_os = "windows")]
+        let exe_path = relink_path.join("dummy.exe");
+        #[cfg(not(target_os = "windows"))]
+        let exe_path = relink_path.join("dummy");
+
+        assert!(compile(&config).is_ok());
+        assert!(link(&config).is_ok());
+        let linked = std::fs::metadata(&exe_path).unwrap().modified().unwrap();
+
+        // nothing changed, the executable is left alone
+        assert!(compile(&config).is_ok());
+        assert!(link(&config).is_ok());
+        assert_eq!(std::fs::metadata(&exe_path).unwrap().modified().unwrap(), linked);
+
+        // another link command line links again
+        config.forge.profiles.get_mut("relink").unwrap().ldflags = Some(vec!["-g".to_string()]);
+        assert!(link(&config).is_ok());
+        let relinked = std::fs::metadata(&exe_path).unwrap().modified().unwrap();
+        assert!(relinked > linked);
+
+        std::fs::remove_dir_all(&relink_path).unwrap();
+    }
+
+    #[test]
+    fn test_glob_sources_with_flags_gcc(){
+        let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
+        let profile_path  = tests_path.join("forge").join("globbed");
+
+        let mut config = dummy_config(true);
+        config.args.command = Build(BuildOptions {
+            debug: false, release: false, compiler: None, profile: Some("globbed".to_string())
+        });
+        config.forge.build.src.push("**/*.c".to_string());
+        config.forge.build.include_dirs.push("include".to_string());
+        config.forge.build.cflags = Some(vec!["-funroll-loops".to_string()]);
+        config.forge.build.ldflags = Some(vec!["-Wl,--as-needed".to_string()]);
+        config.forge.profiles.insert("globbed".to_string(), Profile {
+            inherits: Some("debug".to_string()),
+            cflags: None,
+            ldflags: None,
+            defines: None,
+        });
+
+        // the flag probes of the first build must not become sources of the second
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            results.push(compile(&config).and_then(|_| link(&config)));
+        }
+        std::fs::remove_dir_all(&profile_path).unwrap();
+
+        for res in results {
+            assert!(res.is_ok(), "{:#}", res.unwrap_err());
+        }
+    }
+
+    #[test]
+    fn test_link_error_gcc(){
+        let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
+        let profile_path  = tests_path.join("forge").join("linkerr");
+
+        let mut config = dummy_config(true);
+        config.args.command = Build(BuildOptions {
+            debug: false, release: false, compiler: None, profile: Some("linkerr".to_string())
+        });
+        config.forge.build.src.push("lib.c".to_string());
+        config.forge.build.src.push("main.c".to_string());
+        config.forge.build.include_dirs.push("include".to_string());
+        config.forge.profiles.insert("linkerr".to_string(), Profile {
+            inherits: Some("debug".to_string()),
+            cflags: None,
+            ldflags: None,
+            defines: None,
+        });
+        config.forge.dependencies = Some(Dependencies {
+            libraries: vec!["rustyforge_missing".to_string()],
+            library_paths: Vec::new(),
+            include_dirs: Vec::new(),
+            posix_libraries: Vec::new(),
+        });
+
+        assert!(compile(&config).is_ok());
+        let link_res = link(&config);
+        std::fs::remove_dir_all(&profile_path).unwrap();
+
+        let error = format!("{:#}", link_res.unwrap_err());
+        assert!(error.contains("Could not link target `bin`"));
+    }
+
+    #[test]
+    fn test_workspace_gcc(){
+        let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let ws_path = cwd.join("tests").join("fixtures").join("workspace");
+        let _cwd = CwdGuard::enter(&ws_path);
+        let forge_path = ws_path.join("forge");
+
+        let args = ForgeArgs {
+            verbose: false,
+            verbose_hard: false,
+            jobs: None,
+            keep_going: false,
+            target: None,
+            command: Build(BuildOptions {debug: true, release: false, compiler: None, profile: None}),
+        };
+        let workspace = read_workspace(&ws_path.join("RustyForge.toml")).unwrap().unwrap();
+        let res = run_workspace(&args, &ws_path, &workspace);
+
+        assert!(res.is_ok());
+
+        // both members share the output directory and the build state
+        assert!(forge_path.join(".forge").join("build_state.json").exists());
+        assert!(forge_path.join("debug").join("obj").join("core").join("core").join("core.o").exists());
+        assert!(forge_path.join("debug").join("libs").join("libcore.a").exists());
+        #[cfg(target_os = "windows")]
+        let exe_path = forge_path.join("debug").join("app.exe");
+        #[cfg(not(target_os = "windows"))]
+        let exe_path = forge_path.join("debug").join("app");
+        assert!(exe_path.exists());
+
+        std::fs::remove_dir_all(&forge_path).unwrap();
+    }
+
+    #[test]
+    fn test_clean_rejects_profile_outside_forge_dir() {
+        use clap::Parser;
+        use crate::arguments::CleanOptions;
+        use crate::clean;
+
+        for command in [&["clean", "--profile", ".."][..], &["rebuild", "--profile", ".."], &["run", "--profile", "a/b"]] {
+            let args = std::iter::once("rustyforge").chain(command.iter().copied());
+            assert!(ForgeArgs::try_parse_from(args).is_err(), "{:?} was accepted", command);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let forge_dir = project.join("forge");
+        std::fs::create_dir_all(forge_dir.join("debug")).unwrap();
+        std::fs::write(project.join("RustyForge.toml"), "").unwrap();
+        let mut opt = CleanOptions { debug: false, release: false, libs: false, profile: Some("..".to_string()) };
+        assert!(clean(&forge_dir, &forge_dir, &[], &mut opt).is_err());
+        assert!(project.join("RustyForge.toml").exists());
+        assert!(forge_dir.join("debug").exists());
+    }
+
+    #[test]
+    fn test_clean_keeps_cross_output() {
+        use crate::arguments::CleanOptions;
+        use crate::clean;
+
+        let dir = tempfile::tempdir().unwrap();
+        let forge_dir = dir.path().join("forge");
+        for sub in ["debug", "release", "bench", "libs", "aarch64-linux-gnu/debug", ".forge"] {
+            std::fs::create_dir_all(forge_dir.join(sub)).unwrap();
+        }
+        std::fs::write(forge_dir.join("aarch64-linux-gnu").join("debug").join("app"), "").unwrap();
+        let mut opt = CleanOptions { debug: false, release: false, libs: false, profile: None };
+        clean(&forge_dir, &forge_dir, &["bench".to_string()], &mut opt).unwrap();
+        for removed in ["release", "bench", "libs"] {
+            assert!(!forge_dir.join(removed).exists(), "{} was not removed", removed);
+        }
+        assert!(forge_dir.join("aarch64-linux-gnu").join("debug").join("app").exists());
+        assert!(forge_dir.join(".forge").exists());
+
+        // `clean --target` removes everything of the triple
+        let mut opt = CleanOptions { debug: false, release: false, libs: false, profile: None };
+        let triple_dir = forge_dir.join("aarch64-linux-gnu");
+        clean(&forge_dir, &triple_dir, &[], &mut opt).unwrap();
+        assert!(!triple_dir.join("debug").join("app").exists());
+    }
+
     #[test]
     fn test_broken_project_gcc(){
         let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let org_cwd = cwd.clone();
         let tests_path = cwd.join("tests").join("fixtures").join("broken_project");
+        let _cwd = CwdGuard::enter(&tests_path);
         // make sure the build directory is empty
         let debug_path  = tests_path.join("forge").join("debug");
         clear_dir(&debug_path).unwrap();
 
-        env::set_current_dir(&tests_path).unwrap();
-
         let mut config = dummy_config(true);
         config.forge.build.src.push("main.c".to_string());
 
-        init_hash_cache_json(tests_path.join("forge").join(".forge")).unwrap();
-        
         let compile_res = compile(&config);
         assert!(compile_res.is_err());
-        env::set_current_dir(org_cwd).unwrap();
     }
     #[test]
     fn test_valid_project_clang(){
         let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let org_cwd = cwd.clone();
         let tests_path = cwd.join("tests").join("fixtures").join("valid_project");
+        let _cwd = CwdGuard::enter(&tests_path);
         // make sure the build directory is empty
         let debug_path  = tests_path.join("forge").join("debug");
         // create the debug dir if it does not exist!
@@ -136,8 +458,6 @@ mod integration_tests {
         }
         clear_dir(&debug_path).unwrap();
 
-        env::set_current_dir(&tests_path).unwrap();
-
         let mut config = dummy_config(true);
         config.forge.build.src.push("lib.c".to_string());
         config.forge.build.src.push("main.c".to_string());
@@ -145,16 +465,14 @@ mod integration_tests {
         // set compiler to clang
         config.compiler = CompilerKind::Clang;
         
-        init_hash_cache_json(tests_path.join("forge").join(".forge")).unwrap();
-        
         let compile_res = compile(&config);
         assert!(compile_res.is_ok());
 
         let link_res = link(&config);
         assert!(link_res.is_ok());
 
-        let main_o_path = debug_path.join("main.o");
-        let lib_o_path = debug_path.join("lib.o");
+        let main_o_path = debug_path.join("obj").join("bin").join("main.o");
+        let lib_o_path = debug_path.join("obj").join("bin").join("lib.o");
         #[cfg(target_os = "windows")]
         let exe_path = debug_path.join("dummy.exe");
         #[cfg(not(target_os = "windows"))]
@@ -165,30 +483,23 @@ mod integration_tests {
 
         // delete the build contents
         clear_dir(&debug_path).unwrap();
-
-        env::set_current_dir(org_cwd).unwrap();
     }
 
     #[test]
     fn test_broken_project_clang(){
         let cwd = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let org_cwd = cwd.clone();
         let tests_path = cwd.join("tests").join("fixtures").join("broken_project");
+        let _cwd = CwdGuard::enter(&tests_path);
         // make sure the build directory is empty
         let debug_path  = tests_path.join("forge").join("debug");
         clear_dir(&debug_path).unwrap();
 
-        env::set_current_dir(&tests_path).unwrap();
-
         let mut config = dummy_config(true);
         config.forge.build.src.push("main.c".to_string());
         // set compiler to clang
         config.compiler = CompilerKind::Clang;
 
-        init_hash_cache_json(tests_path.join("forge").join(".forge")).unwrap();
-        
         let compile_res = compile(&config);
         assert!(compile_res.is_err());
-        env::set_current_dir(org_cwd).unwrap();
     }
 }
\ No newline at end of file
@@ -0,0 +1,381 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::config::{CStandard, Warnings};
+
+/// the major and minor version of a compiler
+pub type Version = (u32, u32);
+
+/// explicit tool paths, declared as `[toolchain]`
+#[derive(Deserialize, Debug, Serialize, Default, Clone, PartialEq)]
+pub struct ToolchainConfig {
+    /// the compiler, e.g. `gcc-13`, `/opt/llvm/bin/clang` or `tcc`
+    pub cc: Option<String>,
+    /// the archiver for static libraries, `ar` by default
+    pub ar: Option<String>,
+    /// the linker driver for executables and shared libraries, the compiler by default
+    pub linker: Option<String>,
+}
+
+/// settings for cross compiling to a target triple, declared as `[cross.<triple>]`
+#[derive(Deserialize, Debug, Serialize, Default, Clone, PartialEq)]
+pub struct CrossConfig {
+    /// prefix of the tool names, like `aarch64-linux-gnu-`. gcc-like toolchains use `<triple>-`
+    /// by default, clang-like ones pass `--target=<triple>` to clang instead
+    pub prefix: Option<String>,
+    /// directory holding the headers and libraries of the target
+    pub sysroot: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cflags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ldflags: Vec<String>,
+}
+
+/// the target triple of a cross compiling toolchain, with its settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossTarget {
+    pub triple: String,
+    pub config: CrossConfig,
+}
+
+/// the programs of a toolchain, with the defaults filled in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tools {
+    pub cc: String,
+    pub ar: String,
+    pub linker: String,
+    /// set when compiling for another platform than the host
+    pub cross: Option<CrossTarget>,
+}
+
+impl Tools {
+    pub fn new(cc: String, config: &ToolchainConfig) -> Self {
+        Tools {
+            ar: config.ar.clone().unwrap_or("ar".to_string()),
+            linker: config.linker.clone().unwrap_or(cc.clone()),
+            cc,
+            cross: None,
+        }
+    }
+
+    /// Prefixes the tool names, so `gcc` becomes `aarch64-linux-gnu-gcc`.
+    /// Paths and names that already carry the prefix are kept as they are.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        for tool in [&mut self.cc, &mut self.ar, &mut self.linker] {
+            let is_path = tool.contains('/') || tool.contains('\\');
+            if !is_path && !tool.starts_with(prefix) {
+                *tool = format!("{}{}", prefix, tool);
+            }
+        }
+        self
+    }
+}
+
+/// The operations of a C toolchain. Each returns the command with the arguments
+/// of the operation itself, the flags of the project are added by the caller.
+/// The provided methods use the command line of gcc, which clang understands as well.
+pub trait Toolchain {
+    fn tools(&self) -> &Tools;
+
+    /// the debugger, ready to be followed by the program and its arguments
+    fn debugger(&self) -> Command;
+
+    /// the flag selecting the C standard, an error if the compiler `version` does not know it
+    fn standard_flag(&self, standard: CStandard, version: Option<Version>) -> Result<String>;
+
+    /// the flags of the warning level
+    fn warning_flags(&self, warnings: Warnings, as_errors: bool) -> Vec<String> {
+        let flags: &[&str] = match warnings {
+            Warnings::Default => &[],
+            Warnings::All => &["-Wall"],
+            Warnings::Extra => &["-Wall", "-Wextra"],
+            Warnings::Pedantic => &["-Wall", "-Wextra", "-Wpedantic"],
+            Warnings::None => &["-w"],
+        };
+        let mut flags: Vec<String> = flags.iter().map(|f| f.to_string()).collect();
+        if as_errors {
+            flags.push("-Werror".to_string());
+        }
+        flags
+    }
+
+    /// selects the platform to compile and link for, when cross compiling
+    fn target_args(&self, cmd: &mut Command) {
+        sysroot_args(self.tools(), cmd);
+    }
+
+    /// the compiler, to add flags to before `compile_args`
+    fn compiler(&self) -> Command {
+        let mut cmd = Command::new(&self.tools().cc);
+        self.target_args(&mut cmd);
+        if let Some(cross) = &self.tools().cross {
+            cmd.args(&cross.config.cflags);
+        }
+        cmd
+    }
+
+    /// makes the compiler fail on flags it does not know, for probing them
+    fn reject_unknown_flags(&self, _cmd: &mut Command) {}
+
+    /// compiles `source` into `object`, without linking
+    fn compile_args(&self, cmd: &mut Command, source: &Path, object: &Path) {
+        cmd.arg("-c").arg(source).arg("-o").arg(object);
+    }
+
+    /// lets the compiler write the headers it included into `depfile`, while compiling
+    fn depfile_args(&self, cmd: &mut Command, depfile: &Path) {
+        cmd.arg("-MMD").arg("-MF").arg(depfile);
+    }
+
+    /// creates the static library `archive` from the objects
+    fn archive(&self, archive: &Path, objects: &[PathBuf]) -> Command {
+        let mut cmd = Command::new(&self.tools().ar);
+        cmd.arg("rcs").arg(archive).args(objects);
+        cmd
+    }
+
+    /// the linker driver, for executables and shared libraries
+    fn linker(&self) -> Command {
+        let mut cmd = Command::new(&self.tools().linker);
+        self.target_args(&mut cmd);
+        if let Some(cross) = &self.tools().cross {
+            cmd.args(&cross.config.ldflags);
+        }
+        cmd
+    }
+
+    /// identifies the compiler by its `--version` output, so that switching
+    /// compilers or upgrading one rebuilds everything
+    fn identity(&self) -> Result<String> {
+        let cc = &self.tools().cc;
+        let output = Command::new(cc).arg("--version").output()
+            .with_context(|| format!("Failed to run the compiler: {}", cc))?;
+        if !output.status.success() {
+            bail!("Failed to get the version of the compiler: {}", cc);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// The `-std=` flag of a standard. `since` holds the first version knowing each standard by its year,
+/// before `c23_since` the compiler calls c23 by its draft name c2x.
+/// Compilers without a known version get the flag unchecked.
+fn std_flag(tools: &Tools, standard: CStandard, version: Option<Version>, since: &[(u32, Version)], c23_since: Version) -> Result<String> {
+    let mut name = standard.to_string();
+    if let Some(version) = version {
+        let first = since.iter().find(|(year, _)| *year == standard.year()).map_or((0, 0), |(_, first)| *first);
+        if version < first {
+            bail!(
+                "The C standard {} is not supported by {} {}.{}, it needs version {}.{} or newer",
+                standard, tools.cc, version.0, version.1, first.0, first.1
+            );
+        }
+        if standard.year() == 23 && version < c23_since {
+            name = name.replace("23", "2x");
+        }
+    }
+    Ok(format!("-std={}", name))
+}
+
+/// The version in the `--version` output of gcc or clang, like `gcc (Debian 12.2.0-14) 12.2.0`
+/// or `clang version 16.0.6`. The version follows the word `version`, or ends the first line.
+pub fn compiler_version(identity: &str) -> Option<Version> {
+    let line = identity.lines().next()?;
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let word = match words.iter().position(|w| *w == "version") {
+        Some(i) => words.get(i + 1)?,
+        None => words.last()?,
+    };
+    let mut parts = word.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor))
+}
+
+fn sysroot_args(tools: &Tools, cmd: &mut Command) {
+    if let Some(sysroot) = tools.cross.as_ref().and_then(|c| c.config.sysroot.as_ref()) {
+        cmd.arg(format!("--sysroot={}", sysroot));
+    }
+}
+
+/// gcc, and compilers with the same command line, like `tcc` or cross compilers
+pub struct GccLike {
+    pub tools: Tools,
+}
+
+impl Toolchain for GccLike {
+    fn tools(&self) -> &Tools {
+        &self.tools
+    }
+
+    fn debugger(&self) -> Command {
+        let mut cmd = Command::new("gdb");
+        cmd.arg("--args");
+        cmd
+    }
+
+    fn standard_flag(&self, standard: CStandard, version: Option<Version>) -> Result<String> {
+        std_flag(&self.tools, standard, version, &[(99, (3, 0)), (11, (4, 7)), (17, (8, 0)), (23, (9, 0))], (14, 0))
+    }
+}
+
+/// clang, from any LLVM installation
+pub struct ClangLike {
+    pub tools: Tools,
+}
+
+impl Toolchain for ClangLike {
+    fn tools(&self) -> &Tools {
+        &self.tools
+    }
+
+    fn debugger(&self) -> Command {
+        let mut cmd = Command::new("lldb");
+        cmd.arg("--");
+        cmd
+    }
+
+    fn standard_flag(&self, standard: CStandard, version: Option<Version>) -> Result<String> {
+        std_flag(&self.tools, standard, version, &[(99, (1, 0)), (11, (3, 1)), (17, (6, 0)), (23, (9, 0))], (18, 0))
+    }
+
+    /// clang only warns about unknown warning flags
+    fn reject_unknown_flags(&self, cmd: &mut Command) {
+        cmd.arg("-Werror=unknown-warning-option");
+    }
+
+    /// a single clang compiles for every target, selected by `--target`
+    fn target_args(&self, cmd: &mut Command) {
+        if let Some(cross) = &self.tools.cross {
+            cmd.arg(format!("--target={}", cross.triple));
+        }
+        sysroot_args(&self.tools, cmd);
+    }
+}
+
+/// whether the program runs and reports its version
+pub fn runs(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// whether the program can be started at all, for tools without a `--version` like BSD `ar`
+pub fn exists(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn test_tools_defaults() {
+        let tools = Tools::new("gcc-13".to_string(), &ToolchainConfig::default());
+        assert_eq!(tools, Tools {
+            cc: "gcc-13".to_string(),
+            ar: "ar".to_string(),
+            linker: "gcc-13".to_string(),
+            cross: None,
+        });
+
+        let config = ToolchainConfig {
+            cc: None,
+            ar: Some("llvm-ar".to_string()),
+            linker: Some("/usr/bin/ld.lld".to_string()),
+        };
+        let tools = Tools::new("clang".to_string(), &config);
+        assert_eq!(tools.ar, "llvm-ar");
+        assert_eq!(tools.linker, "/usr/bin/ld.lld");
+    }
+
+    #[test]
+    fn test_gcc_like_commands() {
+        let toolchain = GccLike { tools: Tools::new("/opt/gcc/bin/gcc".to_string(), &ToolchainConfig::default()) };
+
+        let mut cmd = toolchain.compiler();
+        cmd.arg("-O2");
+        toolchain.compile_args(&mut cmd, Path::new("main.c"), Path::new("main.o"));
+        toolchain.depfile_args(&mut cmd, Path::new("main.o.d"));
+        assert_eq!(cmd.get_program(), "/opt/gcc/bin/gcc");
+        assert_eq!(args(&cmd), ["-O2", "-c", "main.c", "-o", "main.o", "-MMD", "-MF", "main.o.d"]);
+
+        let cmd = toolchain.archive(Path::new("libm.a"), &[PathBuf::from("a.o"), PathBuf::from("b.o")]);
+        assert_eq!(cmd.get_program(), "ar");
+        assert_eq!(args(&cmd), ["rcs", "libm.a", "a.o", "b.o"]);
+
+        assert_eq!(toolchain.linker().get_program(), "/opt/gcc/bin/gcc");
+        assert_eq!(toolchain.debugger().get_program(), "gdb");
+    }
+
+    #[test]
+    fn test_cross_tools() {
+        let config = ToolchainConfig { cc: None, ar: None, linker: Some("/opt/cross/bin/ld".to_string()) };
+        let tools = Tools::new("gcc".to_string(), &config).with_prefix("aarch64-linux-gnu-");
+        assert_eq!(tools.cc, "aarch64-linux-gnu-gcc");
+        assert_eq!(tools.ar, "aarch64-linux-gnu-ar");
+        assert_eq!(tools.linker, "/opt/cross/bin/ld");
+        let tools = Tools::new("aarch64-linux-gnu-gcc".to_string(), &config).with_prefix("aarch64-linux-gnu-");
+        assert_eq!(tools.cc, "aarch64-linux-gnu-gcc");
+
+        let cross = CrossTarget {
+            triple: "aarch64-linux-gnu".to_string(),
+            config: CrossConfig {
+                prefix: None,
+                sysroot: Some("/opt/sysroot".to_string()),
+                cflags: vec!["-mcpu=cortex-a72".to_string()],
+                ldflags: vec!["-static".to_string()],
+            },
+        };
+        let mut tools = Tools::new("clang".to_string(), &ToolchainConfig::default());
+        tools.cross = Some(cross);
+        let clang = ClangLike { tools: tools.clone() };
+        assert_eq!(args(&clang.compiler()), ["--target=aarch64-linux-gnu", "--sysroot=/opt/sysroot", "-mcpu=cortex-a72"]);
+        assert_eq!(args(&clang.linker()), ["--target=aarch64-linux-gnu", "--sysroot=/opt/sysroot", "-static"]);
+        let gcc = GccLike { tools };
+        assert_eq!(args(&gcc.compiler()), ["--sysroot=/opt/sysroot", "-mcpu=cortex-a72"]);
+    }
+
+    #[test]
+    fn test_compiler_version() {
+        assert_eq!(compiler_version("gcc (Debian 12.2.0-14+deb12u1) 12.2.0\nCopyright"), Some((12, 2)));
+        assert_eq!(compiler_version("clang version 16.0.6 (Fedora 16.0.6-3.fc38)"), Some((16, 0)));
+        assert_eq!(compiler_version("Ubuntu clang version 18.1.3 (1ubuntu1)"), Some((18, 1)));
+        assert_eq!(compiler_version("tcc version 0.9.27 (x86_64 Linux)"), Some((0, 9)));
+        assert_eq!(compiler_version("some compiler"), None);
+    }
+
+    #[test]
+    fn test_standard_and_warning_flags() {
+        let gcc = GccLike { tools: Tools::new("gcc".to_string(), &ToolchainConfig::default()) };
+        assert_eq!(gcc.standard_flag(CStandard::C11, Some((12, 2))).unwrap(), "-std=c11");
+        assert_eq!(gcc.standard_flag(CStandard::C23, Some((12, 2))).unwrap(), "-std=c2x");
+        assert_eq!(gcc.standard_flag(CStandard::Gnu23, Some((14, 1))).unwrap(), "-std=gnu23");
+        assert_eq!(gcc.standard_flag(CStandard::C23, None).unwrap(), "-std=c23");
+        let error = gcc.standard_flag(CStandard::C17, Some((7, 5))).unwrap_err();
+        assert_eq!(error.to_string(), "The C standard c17 is not supported by gcc 7.5, it needs version 8.0 or newer");
+
+        let clang = ClangLike { tools: Tools::new("clang".to_string(), &ToolchainConfig::default()) };
+        assert_eq!(clang.standard_flag(CStandard::C23, Some((17, 0))).unwrap(), "-std=c2x");
+        assert_eq!(clang.standard_flag(CStandard::C23, Some((18, 1))).unwrap(), "-std=c23");
+        assert!(clang.standard_flag(CStandard::C23, Some((8, 0))).is_err());
+
+        assert!(gcc.warning_flags(Warnings::Default, false).is_empty());
+        assert_eq!(gcc.warning_flags(Warnings::Extra, false), ["-Wall", "-Wextra"]);
+        assert_eq!(clang.warning_flags(Warnings::Pedantic, true), ["-Wall", "-Wextra", "-Wpedantic", "-Werror"]);
+        assert_eq!(clang.warning_flags(Warnings::None, false), ["-w"]);
+    }
+
+    #[test]
+    fn test_clang_like_debugger() {
+        let toolchain = ClangLike { tools: Tools::new("clang-18".to_string(), &ToolchainConfig::default()) };
+        assert_eq!(toolchain.debugger().get_program(), "lldb");
+        assert_eq!(args(&toolchain.debugger()), ["--"]);
+    }
+}
@@ -10,6 +10,7 @@ use crossterm::{
 use crossterm::style::Stylize;
 use crate::arguments::DiscoverOptions;
 use crate::discovery::should_be_ignored;
+use crate::validation::ConfigError;
 
 pub fn verbose_command(cmd: &Command) {
     let (program, args) = format_command(cmd);
@@ -26,6 +27,11 @@ pub fn verbose_command(cmd: &Command) {
     println!("[{}] Running: {} {}", "verbose".bold().yellow() ,clean_program, clean_args);
 }
 
+/// prints a line of verbose output, that is not a command
+pub fn verbose_note(note: &str) {
+    println!("[{}] {}", "verbose".bold().yellow(), note);
+}
+
 pub fn verbose_command_hard(cmd: &Command) {
     let (program, args) = format_command(cmd);
     let args_string = args
@@ -118,4 +124,19 @@ pub fn print_heating(){
 
 pub fn print_cleaning(){
     println!("{}", "Cleaning...".bold())   
-}
\ No newline at end of file
+}
+
+pub fn print_config_error(error: &ConfigError) {
+    eprintln!(
+        "{}:{}:{}: {} {}",
+        error.file.display(), error.line, error.column, "error:".bold().red(), error.message
+    );
+}
+
+pub fn print_error(message: &str) {
+    eprintln!("{} {}", "error:".bold().red(), message);
+}
+
+pub fn print_warning(message: &str) {
+    eprintln!("{} {}", "warning:".bold().yellow(), message);
+}
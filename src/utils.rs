@@ -1,104 +1,48 @@
 use std::path::Path;
 use std::process::Command;
-use crate::config::{CompilerKind, Config};
+use crate::config::{CompilerKind, Config, Profile};
 use crossterm::style::{Stylize};
-use crate::arguments;
-use crate::arguments::Command::*;
 use crate::arguments::{CleanOptions, BuildOptions};
-
-pub fn check_compiler(cfg: &mut Config) {
-    let comp = cfg.compiler;
-    let available = match comp {
-        CompilerKind::GCC => is_gcc_available(),
-        CompilerKind::Clang => is_clang_available(),
-        CompilerKind::MSVC => false, // TODO: Implement MSVC support
-    };
-    if !available {
-        eprintln!("Compiler is not available: {}", comp.to_string().red());
-        println!("Trying fallback to default compiler: gcc");
-        if is_gcc_available() {
-            cfg.compiler = CompilerKind::GCC;
-        } else {
-            eprintln!("[{}]", "Fallback compiler not found!\nAborting...".red());
-            std::process::exit(1);
-        }   
-    }  
-}
-
-fn is_clang_available() -> bool {
-    Command::new("clang")
-        .arg("--version")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false)
-}
-
-
-fn is_gcc_available() -> bool {
-    Command::new("gcc")
-        .arg("--version")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false)
-}
-
-pub fn is_valid_cflag(flag: &str) -> bool {
-    let forbidden = ["-Wall", "-Wextra", "-DDEBUG", "-DNDEBUG", "-DRELEASE"];
-
-    if forbidden.contains(&flag) {
-        eprintln!("Warning: Flag '{}' is handled internally and should not be set explicitly.", flag);
-        return false;
-    }
-
-    let valid_flags = [
-        "-O0", "-O1", "-O2", "-O3", "-Os", "-Ofast",
-        "-g", "-ggdb", "-g3",
-        "-Werror", "-Wpedantic", "-Wshadow", "-Wconversion", "-Wformat", "-Wunused",
-        "-Wsign-compare", "-Wfloat-equal",
-        "-I", "-isystem", "-D",
-        "-std=", "-fPIC", "-fvisibility=", "-march=", "-mtune=",
-        "-pthread", "-pipe", "-c", "-S",
-    ];
-
-    for valid in valid_flags {
-        if valid.ends_with('=') || valid == "-I" || valid == "-D" || valid == "-isystem" {
-            if flag.starts_with(valid) {
-                return true;
-            }
-        } else if flag == valid {
-            return true;
+use crate::toolchain::{exists, runs};
+use anyhow::{bail, Result};
+
+/// Checks that the configured compiler, archiver and linker can be run.
+/// Only the default compiler of a family falls back to gcc, a configured one has to exist.
+pub fn check_compiler(cfg: &mut Config) -> Result<()> {
+    let cc = cfg.cc();
+    if let Some(triple) = cfg.target() {
+        // the host compiler is no fallback for another platform
+        let tools = cfg.toolchain()?.tools().clone();
+        if !runs(&tools.cc) {
+            bail!("Compiler for {} is not available: {}", triple, tools.cc);
+        }
+        if !exists(&tools.ar) {
+            bail!("Archiver for {} is not available: {}", triple, tools.ar);
         }
+        if !runs(&tools.linker) {
+            bail!("Linker for {} is not available: {}", triple, tools.linker);
+        }
+        return Ok(());
     }
-
-    eprintln!("Invalid compiler flag '{}': Not recognized as valid gcc C compiler flag.", flag);
-    false
-}
-
-pub fn is_valid_ldflag(flag: &str) -> bool {
-    let valid_ldflags = [
-        "-L",      // Library path
-        "-l",      // Link library
-        "-shared", // Shared library
-        "-static", // Static linking
-        "-pthread",
-        "-Wl,",    // Pass option to linker
-        "-rdynamic",
-        "-pie",
-        "-fPIC",
-    ];
-
-    for valid in valid_ldflags {
-        if valid.ends_with(',') {
-            if flag.starts_with(valid) {
-                return true;
-            }
-        } else if flag == valid || flag.starts_with(valid) {
-            return true;
+    if cfg.compiler == CompilerKind::MSVC || !runs(&cc) {
+        if cfg.cc.is_some() {
+            bail!("Compiler is not available: {}", cc);
+        }
+        eprintln!("Compiler is not available: {}", cc.red());
+        println!("Trying fallback to default compiler: gcc");
+        if !runs("gcc") {
+            bail!("Fallback compiler not found!");
         }
+        cfg.compiler = CompilerKind::GCC;
     }
-
-    eprintln!("Invalid linker flag '{}': Not recognized as valid gcc linker flag.", flag);
-    false
+    let toolchain = cfg.forge.toolchain.clone().unwrap_or_default();
+    if let Some(ar) = cfg.env.ar.as_ref().or(toolchain.ar.as_ref()).filter(|ar| !exists(ar)) {
+        bail!("Archiver is not available: {}", ar);
+    }
+    if let Some(linker) = toolchain.linker.as_ref().filter(|linker| !runs(linker)) {
+        bail!("Linker is not available: {}", linker);
+    }
+    Ok(())
 }
 
 pub fn format_command(cmd: &Command) -> (String, Vec<String>) {
@@ -124,56 +68,21 @@ pub fn strip_cwd(arg: &str, cwd: &Path) -> String {
     }
 }
 
-// TODO: Change name of subcommands
-pub fn add_build_flags(command: &arguments::Command, cmd: &mut Command) {
-    match command { 
-        Run(opt) => {
-            if opt.debug {
-                add_debug_cflags(cmd);
-            } 
-            else {
-                add_release_cflags(cmd);
-            }
-        }
-        Build(opt) => {
-            if opt.debug {
-                add_debug_cflags(cmd);
-            } 
-            else {
-                add_release_cflags(cmd);
-            }
-        }
-        Rebuild(opt) => {
-            if opt.debug {
-                add_debug_cflags(cmd);
-            } 
-            else {
-                add_release_cflags(cmd);
-            }
-        }
-        _ => {}
+/// adds the compiler flags and defines of the build profile
+pub fn add_profile_cflags(profile: &Profile, cmd: &mut Command) {
+    for flag in profile.cflags.iter().flatten() {
+        cmd.arg(flag);
+    }
+    for define in profile.defines.iter().flatten() {
+        cmd.arg(format!("-D{}", define));
     }
 }
 
-pub fn add_debug_cflags(cmd: &mut Command) {
-    cmd.arg("-g").arg("-O0").arg("-Wall").arg("-Wextra").arg("-DDEBUG");
-}
-
-pub fn add_release_cflags(cmd: &mut Command) {
-    cmd.arg("-O3").arg("-Wall").arg("-Wextra").arg("-DRELEASE").arg("-DNDEBUG");
-}
-
-pub fn format_lib_name(name: &mut String){
-    let new_name = format!("lib{}.a", name);
-    *name = new_name;
-}
-
-pub fn format_shared_lib_name(name: &mut String){
-    #[cfg(target_os = "windows")]
-    let new_name = format!("lib{}.dll", name);
-    #[cfg(target_os = "linux")]
-    let new_name = format!("lib{}.so", name);
-    *name = new_name;
+/// adds the linker flags of the build profile
+pub fn add_profile_ldflags(profile: &Profile, cmd: &mut Command) {
+    for flag in profile.ldflags.iter().flatten() {
+        cmd.arg(flag);
+    }
 }
 
 pub fn derive_clean_options(build_opt: &BuildOptions) -> CleanOptions {
@@ -181,91 +90,7 @@ pub fn derive_clean_options(build_opt: &BuildOptions) -> CleanOptions {
         debug: build_opt.debug,
         release: build_opt.release,
         libs: false,
+        profile: build_opt.profile.clone(),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_valid_exact_flags() {
-        let flags = [
-            "-O2", "-O3", "-g", "-Werror", "-pthread", "-pipe", "-c", "-S"
-        ];
-        for flag in flags {
-            assert!(is_valid_cflag(flag), "Expected '{}' to be valid", flag);
-        }
-    }
-
-    #[test]
-    fn test_valid_prefix_flags() {
-        let flags = [
-            "-I/usr/include",
-            "-isystem/usr/local/include",
-            "-DDEBUG_MODE",
-            "-std=c99",
-            "-fvisibility=hidden",
-            "-march=native",
-            "-mtune=core2"
-        ];
-        for flag in flags {
-            assert!(is_valid_cflag(flag), "Expected '{}' to be valid", flag);
-        }
-    }
-
-    #[test]
-    fn test_forbidden_flags() {
-        let forbidden = ["-Wall", "-Wextra", "-DDEBUG", "-DNDEBUG"];
-        for flag in forbidden {
-            assert!(!is_valid_cflag(flag), "Expected '{}' to be forbidden", flag);
-        }
-    }
-
-    #[test]
-    fn test_invalid_flags() {
-        let invalid = ["-O4", "-Wbanana", "-funroll-loops", "--weirdflag"];
-        for flag in invalid {
-            assert!(!is_valid_cflag(flag), "Expected '{}' to be invalid", flag);
-        }
-    }
-    #[test]
-    fn test_valid_ldflags_exact() {
-        let flags = [
-            "-shared",
-            "-static",
-            "-pthread",
-            "-rdynamic",
-            "-pie",
-            "-fPIC",
-        ];
-        for flag in flags {
-            assert!(is_valid_ldflag(flag), "Expected '{}' to be valid", flag);
-        }
-    }
-
-    #[test]
-    fn test_valid_ldflags_prefix() {
-        let flags = [
-            "-L/usr/lib",
-            "-lssl",
-            "-lcrypto",
-            "-Wl,--as-needed",
-        ];
-        for flag in flags {
-            assert!(is_valid_ldflag(flag), "Expected '{}' to be valid", flag);
-        }
-    }
-
-    #[test]
-    fn test_invalid_ldflags() {
-        let flags = [
-            "-Zweird",
-            "-Xlinker",
-            "-unknownflag",
-        ];
-        for flag in flags {
-            assert!(!is_valid_ldflag(flag), "Expected '{}' to be invalid", flag);
-        }
-    }
-}
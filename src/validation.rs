@@ -0,0 +1,538 @@
+use std::fmt::Display;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Result};
+use toml_edit::{ImDocument, Item, TableLike};
+use crate::config::{Forge, Profile};
+use crate::discovery::{is_glob, source_glob};
+use crate::ui::print_config_error;
+use crate::workspace::read_workspace;
+
+const TOP_LEVEL_KEYS: [&str; 9] = ["project", "build", "dependencies", "target", "profile", "run", "toolchain", "cross", "workspace"];
+const PROJECT_KEYS: [&str; 2] = ["name", "targets"];
+const BUILD_KEYS: [&str; 11] = [
+    "compiler", "src", "exclude", "include_dirs", "output", "cflags", "ldflags",
+    "flag_policy", "c_standard", "warnings", "warnings_as_errors",
+];
+const TARGET_KEYS: [&str; 8] = ["name", "kind", "src", "exclude", "include_dirs", "cflags", "ldflags", "output"];
+const PROFILE_KEYS: [&str; 4] = ["inherits", "cflags", "ldflags", "defines"];
+const DEPENDENCY_KEYS: [&str; 4] = ["libraries", "library_paths", "include_dirs", "posix_libraries"];
+const TOOLCHAIN_KEYS: [&str; 3] = ["cc", "ar", "linker"];
+const CROSS_KEYS: [&str; 4] = ["prefix", "sysroot", "cflags", "ldflags"];
+const RUN_KEYS: [&str; 3] = ["env", "cwd", "args"];
+const WORKSPACE_KEYS: [&str; 1] = ["members"];
+const TARGET_KINDS: [&str; 3] = ["bin", "static", "shared"];
+const FLAG_POLICIES: [&str; 3] = ["strict", "warn", "off"];
+const C_STANDARDS: [&str; 8] = ["c99", "c11", "c17", "c23", "gnu99", "gnu11", "gnu17", "gnu23"];
+const WARNING_LEVELS: [&str; 5] = ["default", "all", "extra", "pedantic", "none"];
+
+/// a problem in a RustyForge.toml, located in the source file
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.column, self.message)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PathKind {
+    File,
+    Dir,
+    /// an exclude entry, which does not have to exist
+    Pattern,
+}
+
+struct Validator<'a> {
+    file: &'a Path,
+    source: &'a str,
+    /// directory the paths in the file are relative to
+    root: &'a Path,
+    errors: Vec<ConfigError>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, span: Option<Range<usize>>, message: String) {
+        let (line, column) = match span {
+            Some(span) => line_column(self.source, span.start),
+            None => (1, 1),
+        };
+        if self.errors.iter().any(|e| e.line == line && e.column == column && e.message == message) {
+            return;
+        }
+        self.errors.push(ConfigError { file: self.file.to_path_buf(), line, column, message });
+    }
+
+    fn check_keys(&mut self, table: &dyn TableLike, allowed: &[&str], section: &str) {
+        for (key, _) in table.iter() {
+            if !allowed.contains(&key) {
+                let span = table.get_key_value(key).and_then(|(k, _)| k.span());
+                self.error(span, format!("unknown key `{}` in {}", key, section));
+            }
+        }
+    }
+
+    fn check_paths(&mut self, table: &dyn TableLike, key: &str, kind: PathKind, section: &str) {
+        let Some(array) = table.get(key).and_then(Item::as_array) else {
+            return;
+        };
+        for value in array.iter() {
+            let Some(path) = value.as_str() else {
+                continue;
+            };
+            let full_path = self.root.join(path);
+            let message = match kind {
+                // glob patterns are expanded at build time, they only have to be valid
+                PathKind::File | PathKind::Pattern if is_glob(path) => match source_glob(path) {
+                    Ok(_) => continue,
+                    Err(_) => format!("invalid glob pattern `{}` in {}.{}", path, section, key),
+                },
+                PathKind::Pattern => continue,
+                PathKind::File if !full_path.is_file() => {
+                    format!("source file `{}` in {}.{} does not exist", path, section, key)
+                }
+                PathKind::Dir if !full_path.is_dir() => {
+                    format!("directory `{}` in {}.{} does not exist", path, section, key)
+                }
+                _ => continue,
+            };
+            self.error(value.span(), message);
+        }
+    }
+
+    fn check_project(&mut self, root: &dyn TableLike, has_targets: bool) {
+        let Some(project) = root.get("project").and_then(Item::as_table_like) else {
+            return;
+        };
+        self.check_keys(project, &PROJECT_KEYS, "[project]");
+        let Some((key, item)) = project.get_key_value("targets") else {
+            return;
+        };
+        if has_targets {
+            self.error(key.span(), "`project.targets` conflicts with the [[target]] tables".to_string());
+        }
+        for value in item.as_array().into_iter().flatten() {
+            if let Some(kind) = value.as_str() {
+                if !TARGET_KINDS.contains(&kind) {
+                    self.error(value.span(), format!("unknown target kind `{}`, expected one of [bin, static, shared]", kind));
+                }
+            }
+        }
+    }
+
+    /// reports a value of `key` that is not one of `choices`
+    fn check_choice(&mut self, table: &dyn TableLike, key: &str, choices: &[&str], section: &str) {
+        if let Some(item) = table.get(key) {
+            if !item.as_str().is_some_and(|value| choices.contains(&value)) {
+                self.error(item.span(), format!("invalid {}.{}, expected one of [{}]", section, key, choices.join(", ")));
+            }
+        }
+    }
+
+    fn check_build(&mut self, root: &dyn TableLike, has_targets: bool) {
+        let Some(build) = root.get("build").and_then(Item::as_table_like) else {
+            return;
+        };
+        self.check_keys(build, &BUILD_KEYS, "[build]");
+        self.check_paths(build, "src", PathKind::File, "build");
+        self.check_paths(build, "exclude", PathKind::Pattern, "build");
+        self.check_paths(build, "include_dirs", PathKind::Dir, "build");
+        self.check_choice(build, "flag_policy", &FLAG_POLICIES, "build");
+        self.check_choice(build, "c_standard", &C_STANDARDS, "build");
+        self.check_choice(build, "warnings", &WARNING_LEVELS, "build");
+        if has_targets {
+            for key in ["src", "output"] {
+                if let Some((key, _)) = build.get_key_value(key) {
+                    self.error(key.span(), format!("`build.{}` conflicts with the [[target]] tables", key));
+                }
+            }
+        }
+    }
+
+    fn check_targets(&mut self, root: &dyn TableLike) {
+        let Some(targets) = root.get("target").and_then(Item::as_array_of_tables) else {
+            if let Some((key, _)) = root.get_key_value("target") {
+                self.error(key.span(), "`target` must be declared as [[target]] tables".to_string());
+            }
+            return;
+        };
+        let mut names: Vec<&str> = Vec::new();
+        for target in targets.iter() {
+            let name = target.get("name").and_then(Item::as_str).unwrap_or("?");
+            let section = format!("[[target]] `{}`", name);
+            self.check_keys(target, &TARGET_KEYS, &section);
+            self.check_paths(target, "src", PathKind::File, "target");
+            self.check_paths(target, "exclude", PathKind::Pattern, "target");
+            self.check_paths(target, "include_dirs", PathKind::Dir, "target");
+            if let Some(item) = target.get("kind") {
+                match item.as_str() {
+                    Some(kind) if TARGET_KINDS.contains(&kind) => {}
+                    _ => self.error(item.span(), format!(
+                        "invalid kind for target `{}`, expected one of [bin, static, shared]", name
+                    )),
+                }
+            }
+            if let Some(item) = target.get("name") {
+                if names.contains(&name) {
+                    self.error(item.span(), format!("duplicate target name `{}`", name));
+                }
+                names.push(name);
+            }
+        }
+    }
+
+    fn check_profiles(&mut self, root: &dyn TableLike) {
+        let Some(profiles) = root.get("profile").and_then(Item::as_table_like) else {
+            return;
+        };
+        for (name, item) in profiles.iter() {
+            let Some(profile) = item.as_table_like() else {
+                continue;
+            };
+            self.check_keys(profile, &PROFILE_KEYS, &format!("[profile.{}]", name));
+            if let Some(parent_item) = profile.get("inherits") {
+                if let Some(parent) = parent_item.as_str() {
+                    if !profiles.contains_key(parent) && Profile::builtin(parent).is_none() {
+                        self.error(parent_item.span(), format!("profile `{}` inherits unknown profile `{}`", name, parent));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_dependencies(&mut self, root: &dyn TableLike) {
+        let Some(deps) = root.get("dependencies").and_then(Item::as_table_like) else {
+            return;
+        };
+        self.check_keys(deps, &DEPENDENCY_KEYS, "[dependencies]");
+        self.check_paths(deps, "include_dirs", PathKind::Dir, "dependencies");
+        self.check_paths(deps, "library_paths", PathKind::Dir, "dependencies");
+    }
+
+    fn check_run(&mut self, root: &dyn TableLike) {
+        let Some(run) = root.get("run").and_then(Item::as_table_like) else {
+            return;
+        };
+        self.check_keys(run, &RUN_KEYS, "[run]");
+        if let Some(value) = run.get("cwd").and_then(Item::as_value) {
+            if let Some(cwd) = value.as_str() {
+                if !self.root.join(cwd).is_dir() {
+                    self.error(value.span(), format!("directory `{}` in run.cwd does not exist", cwd));
+                }
+            }
+        }
+    }
+
+    fn check_toolchain(&mut self, root: &dyn TableLike) {
+        let Some(toolchain) = root.get("toolchain").and_then(Item::as_table_like) else {
+            return;
+        };
+        self.check_keys(toolchain, &TOOLCHAIN_KEYS, "[toolchain]");
+    }
+
+    fn check_cross(&mut self, root: &dyn TableLike) {
+        let Some(targets) = root.get("cross").and_then(Item::as_table_like) else {
+            return;
+        };
+        for (triple, item) in targets.iter() {
+            if let Some(cross) = item.as_table_like() {
+                self.check_keys(cross, &CROSS_KEYS, &format!("[cross.{}]", triple));
+            }
+        }
+    }
+
+    fn check_workspace(&mut self, root: &dyn TableLike) {
+        let Some(workspace) = root.get("workspace").and_then(Item::as_table_like) else {
+            return;
+        };
+        self.check_keys(workspace, &WORKSPACE_KEYS, "[workspace]");
+        if let Some((key, _)) = root.get_key_value("project") {
+            self.error(key.span(), "[project] conflicts with [workspace], a workspace root is not a project".to_string());
+        }
+        for value in workspace.get("members").and_then(Item::as_array).into_iter().flatten() {
+            if let Some(member) = value.as_str() {
+                if !self.root.join(member).join("RustyForge.toml").is_file() {
+                    self.error(value.span(), format!("workspace member `{}` has no RustyForge.toml", member));
+                }
+            }
+        }
+    }
+}
+
+/// converts a byte offset into a 1-based line and column
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+/// Validates a RustyForge.toml and returns every problem found.
+/// Paths in the file are checked relative to the directory of the file.
+pub fn validate_config(toml_path: &Path) -> Result<Vec<ConfigError>> {
+    let source = fs::read_to_string(toml_path)?;
+    let root = toml_path.parent().unwrap_or(Path::new("."));
+    let file = toml_path.file_name().map(Path::new).unwrap_or(toml_path);
+    let mut validator = Validator { file, source: &source, root, errors: Vec::new() };
+
+    let document = match ImDocument::parse(source.as_str()) {
+        Ok(document) => document,
+        Err(e) => {
+            validator.error(e.span(), e.message().trim().to_string());
+            return Ok(validator.errors);
+        }
+    };
+    let table = document.as_table();
+    validator.check_keys(table, &TOP_LEVEL_KEYS, "the top level");
+
+    if table.contains_key("workspace") {
+        validator.check_workspace(table);
+        if let Err(e) = read_workspace(toml_path) {
+            validator.error(None, format!("{:#}", e));
+        }
+        return Ok(validator.errors);
+    }
+
+    let has_targets = table.contains_key("target");
+    validator.check_project(table, has_targets);
+    validator.check_build(table, has_targets);
+    validator.check_targets(table);
+    validator.check_profiles(table);
+    validator.check_dependencies(table);
+    validator.check_run(table);
+    validator.check_toolchain(table);
+    validator.check_cross(table);
+
+    // wrong value types and missing keys are found by deserializing
+    if let Err(e) = toml::from_str::<Forge>(&source) {
+        // the schema walk may have reported the same problem in its own words
+        let location = e.span().map(|span| line_column(&source, span.start));
+        if !validator.errors.iter().any(|error| Some((error.line, error.column)) == location) {
+            validator.error(e.span(), e.message().to_string());
+        }
+    }
+    validator.errors.sort_by_key(|e| (e.line, e.column));
+    Ok(validator.errors)
+}
+
+/// validates a RustyForge.toml, printing every problem found
+pub fn check_config(toml_path: &Path) -> Result<()> {
+    let errors = validate_config(toml_path)?;
+    if errors.is_empty() {
+        return Ok(());
+    }
+    for error in &errors {
+        print_config_error(error);
+    }
+    bail!("{} problem(s) found in {}", errors.len(), toml_path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn validate(contents: &str) -> Vec<ConfigError> {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("main.c"), "int main(void) { return 0; }").unwrap();
+        fs::create_dir(dir.path().join("include")).unwrap();
+        let toml_path = dir.path().join("RustyForge.toml");
+        fs::write(&toml_path, contents).unwrap();
+        validate_config(&toml_path).unwrap()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let errors = validate(r#"
+[project]
+name = "demo"
+targets = ["bin"]
+
+[build]
+src = ["main.c"]
+include_dirs = ["include"]
+output = "demo"
+"#);
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_reports_all_problems_with_location() {
+        let errors = validate(r#"[project]
+name = "demo"
+targets = ["bin", "dll"]
+
+[build]
+srcs = ["main.c"]
+src = ["missing.c"]
+include_dirs = ["nope"]
+"#);
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors.len(), 4, "{:?}", messages);
+        assert_eq!(messages[0], "RustyForge.toml:3:19: unknown target kind `dll`, expected one of [bin, static, shared]");
+        assert_eq!(messages[1], "RustyForge.toml:6:1: unknown key `srcs` in [build]");
+        assert!(messages[2].starts_with("RustyForge.toml:7:8: source file `missing.c`"));
+        assert!(messages[3].starts_with("RustyForge.toml:8:17: directory `nope`"));
+    }
+
+    #[test]
+    fn test_glob_sources() {
+        let errors = validate(r#"[project]
+name = "demo"
+targets = ["bin"]
+
+[build]
+src = ["**/*.c", "src/[.c"]
+exclude = ["tests/**", "missing.c"]
+"#);
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec!["RustyForge.toml:6:18: invalid glob pattern `src/[.c` in build.src"]);
+    }
+
+    #[test]
+    fn test_conflicting_targets() {
+        let errors = validate(r#"[project]
+name = "demo"
+targets = ["bin"]
+
+[[target]]
+name = "app"
+kind = "exe"
+src = ["main.c"]
+
+[[target]]
+name = "app"
+kind = "bin"
+"#);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert!(messages.contains(&"`project.targets` conflicts with the [[target]] tables"));
+        assert!(messages.contains(&"invalid kind for target `app`, expected one of [bin, static, shared]"));
+        assert!(messages.contains(&"duplicate target name `app`"));
+    }
+
+    #[test]
+    fn test_syntax_and_type_errors() {
+        let errors = validate("[project\nname = \"demo\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 1);
+
+        let errors = validate("[project]\nname = 5\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 8));
+    }
+
+    #[test]
+    fn test_problems_at_the_same_location() {
+        let source = "[build]\nwarnings = { level = 1 }\n";
+        let mut validator = Validator { file: Path::new("forge.toml"), source, root: Path::new("."), errors: Vec::new() };
+        validator.error(Some(19..20), "unknown key `level` in [build]".to_string());
+        validator.error(Some(19..20), "`build.warnings` must be a string".to_string());
+        validator.error(Some(19..20), "unknown key `level` in [build]".to_string());
+        let messages: Vec<_> = validator.errors.iter().map(|e| (e.line, e.column, e.message.as_str())).collect();
+        assert_eq!(messages, [(2, 12, "unknown key `level` in [build]"), (2, 12, "`build.warnings` must be a string")]);
+    }
+
+    #[test]
+    fn test_unknown_profile_parent() {
+        let errors = validate(r#"[project]
+name = "demo"
+
+[profile.bench]
+inherits = "fast"
+"#);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "profile `bench` inherits unknown profile `fast`");
+    }
+
+    #[test]
+    fn test_run_section() {
+        let errors = validate(r#"[project]
+name = "demo"
+
+[run]
+cwd = "include"
+args = ["--fast"]
+env = { LOG = "debug" }
+"#);
+        assert!(errors.is_empty());
+
+        let errors = validate(r#"[project]
+name = "demo"
+
+[run]
+cwd = "data"
+stdin = "input.txt"
+"#);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["directory `data` in run.cwd does not exist", "unknown key `stdin` in [run]"]);
+    }
+
+    #[test]
+    fn test_flag_policy() {
+        let errors = validate(r#"[project]
+name = "demo"
+
+[build]
+flag_policy = "strict"
+"#);
+        assert!(errors.is_empty());
+
+        let errors = validate(r#"[project]
+name = "demo"
+
+[build]
+flag_policy = "pedantic"
+"#);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["invalid build.flag_policy, expected one of [strict, warn, off]"]);
+    }
+
+    #[test]
+    fn test_language_settings() {
+        let errors = validate(r#"[project]
+name = "demo"
+
+[build]
+c_standard = "gnu11"
+warnings = "pedantic"
+warnings_as_errors = true
+"#);
+        assert!(errors.is_empty());
+
+        let errors = validate(r#"[project]
+name = "demo"
+
+[build]
+c_standard = "c89"
+warnings = "everything"
+"#);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, [
+            "invalid build.c_standard, expected one of [c99, c11, c17, c23, gnu99, gnu11, gnu17, gnu23]",
+            "invalid build.warnings, expected one of [default, all, extra, pedantic, none]",
+        ]);
+    }
+
+    #[test]
+    fn test_cross_section() {
+        let errors = validate(r#"[project]
+name = "demo"
+
+[cross.aarch64-linux-gnu]
+sysroot = "/opt/sysroot"
+cflags = ["-mcpu=cortex-a72"]
+
+[cross.x86_64-w64-mingw32]
+linker = "ld"
+"#);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["unknown key `linker` in [cross.x86_64-w64-mingw32]"]);
+    }
+}
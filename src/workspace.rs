@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use anyhow::{bail, Context, Result};
+use crate::config::{parse_forge_file, Forge, TargetKind};
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct Workspace {
+    pub members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct WorkspaceFile {
+    workspace: Option<Workspace>,
+}
+
+/// a single project of a workspace
+#[derive(Debug)]
+pub struct Member {
+    pub name: String,
+    pub path: PathBuf,
+    pub forge: Forge,
+}
+
+/// reads the `[workspace]` table of a RustyForge.toml, if there is one
+pub fn read_workspace(toml_path: &Path) -> Result<Option<Workspace>> {
+    if !toml_path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(toml_path)?;
+    let file: WorkspaceFile = toml::from_str(&contents)
+        .with_context(|| format!("Could not parse workspace: {}", toml_path.display()))?;
+    Ok(file.workspace)
+}
+
+/// loads all members of the workspace, ordered so that every member
+/// is built after the members whose libraries it links against
+pub fn load_members(root: &Path, workspace: &Workspace) -> Result<Vec<Member>> {
+    let mut members: Vec<Member> = Vec::new();
+    for member in &workspace.members {
+        let path = root.join(member);
+        let toml_path = path.join("RustyForge.toml");
+        if !toml_path.exists() {
+            bail!("Workspace member has no RustyForge.toml: {}", member);
+        }
+        let forge = parse_forge_file(toml_path.to_str().unwrap())
+            .map_err(|e| anyhow::anyhow!("Could not parse {}: {}", toml_path.display(), e))?;
+        let name = forge.project.name.clone();
+        if members.iter().any(|m| m.name == name) {
+            bail!("Duplicate workspace member name: {}", name);
+        }
+        members.push(Member { name, path, forge });
+    }
+    order_members(members)
+}
+
+/// names of the members whose library outputs are linked by the given member
+fn member_dependencies(member: &Member, members: &[Member]) -> Result<Vec<String>> {
+    let libraries = match &member.forge.dependencies {
+        Some(deps) => &deps.libraries,
+        None => return Ok(Vec::new()),
+    };
+    let mut deps = Vec::new();
+    for other in members {
+        if other.name == member.name {
+            continue;
+        }
+        let provides = other.forge.resolve_targets()?.iter().any(|t| {
+            t.kind != TargetKind::Bin && libraries.iter().any(|l| l == t.output_name())
+        });
+        if provides {
+            deps.push(other.name.clone());
+        }
+    }
+    Ok(deps)
+}
+
+/// sorts the members topologically, keeping the declared order where possible
+fn order_members(members: Vec<Member>) -> Result<Vec<Member>> {
+    let mut deps = Vec::new();
+    for member in &members {
+        deps.push(member_dependencies(member, &members)?);
+    }
+
+    let mut remaining: Vec<(Member, Vec<String>)> = members.into_iter().zip(deps).collect();
+    let mut ordered: Vec<Member> = Vec::new();
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|(_, deps)| {
+            deps.iter().all(|d| ordered.iter().any(|m| &m.name == d))
+        });
+        match ready {
+            Some(i) => ordered.push(remaining.remove(i).0),
+            None => {
+                let names: Vec<&str> = remaining.iter().map(|(m, _)| m.name.as_str()).collect();
+                bail!("Dependency cycle between workspace members: {}", names.join(", "));
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, toml: &str) -> Member {
+        Member {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            forge: toml::from_str(toml).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_order_members_by_library_dependencies() {
+        let app = member("app", r#"
+            [project]
+            name = "app"
+            targets = ["bin"]
+
+            [dependencies]
+            libraries = ["core"]
+            library_paths = []
+            include_dirs = []
+            posix_libraries = []
+        "#);
+        let core = member("core", r#"
+            [project]
+            name = "core"
+
+            [[target]]
+            name = "core"
+            kind = "static"
+        "#);
+        let ordered = order_members(vec![app, core]).unwrap();
+        let names: Vec<&str> = ordered.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["core", "app"]);
+    }
+
+    #[test]
+    fn test_order_members_cycle() {
+        let deps = r#"
+            [dependencies]
+            library_paths = []
+            include_dirs = []
+            posix_libraries = []
+        "#;
+        let a = member("a", &format!(r#"
+            [project]
+            name = "a"
+            targets = ["static"]
+            [build]
+            output = "a"
+            {}
+            libraries = ["b"]
+        "#, deps));
+        let b = member("b", &format!(r#"
+            [project]
+            name = "b"
+            targets = ["static"]
+            [build]
+            output = "b"
+            {}
+            libraries = ["a"]
+        "#, deps));
+        assert!(order_members(vec![a, b]).is_err());
+    }
+
+    #[test]
+    fn test_read_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_path = dir.path().join("RustyForge.toml");
+        fs::write(&toml_path, "[workspace]\nmembers = [\"core\", \"app\"]\n").unwrap();
+        let workspace = read_workspace(&toml_path).unwrap().unwrap();
+        assert_eq!(workspace.members, vec!["core".to_string(), "app".to_string()]);
+
+        fs::write(&toml_path, "[project]\nname = \"app\"\n").unwrap();
+        assert!(read_workspace(&toml_path).unwrap().is_none());
+    }
+}
@@ -0,0 +1,7 @@
+#include <stdio.h>
+#include "core.h"
+
+int main(void) {
+    printf("%d\n", core_answer());
+    return 0;
+}
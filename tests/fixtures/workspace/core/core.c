@@ -0,0 +1,5 @@
+#include "core.h"
+
+int core_answer(void) {
+    return 42;
+}
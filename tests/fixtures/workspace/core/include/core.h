@@ -0,0 +1,6 @@
+#ifndef CORE_H
+#define CORE_H
+
+int core_answer(void);
+
+#endif